@@ -1,11 +1,19 @@
 use crate::{
     app::ClicksEditorApp,
+    click::ClickRenderer,
+    cueutils,
+    ltc::LtcRenderer,
+    meter::{Meter, Meters},
+    notify::Level,
+    panel::{chase::ChasePrompt, meter::MeterPrompt, unsaved::UnsavedContinuation},
+    tempomap::TempoMap,
+    timecode::Timecode,
 };
 use common::{
     beat::Beat,
     cue::{Cue, CueMetadata, Show},
     event::{Event, EventDescription, JumpModeChange, JumpRequirement},
-    mem::{smpte::TimecodeInstant, str::StaticString},
+    mem::str::StaticString,
 };
 use egui::{Color32, Image, Key, KeyboardShortcut, ModifierNames, Modifiers};
 
@@ -24,7 +32,10 @@ pub struct Action {
 impl Action {
     pub fn run(&self, app: &mut ClicksEditorApp) {
         app.last_action = Some(self.clone());
-        (self.function)(app)
+        // Commit pending panel edits first, so the action becomes an undo step of its own
+        app.history.commit(&app.project_file);
+        (self.function)(app);
+        app.history.commit(&app.project_file);
     }
 
     fn hotkey_str(&self) -> String {
@@ -177,7 +188,9 @@ pub fn all_actions() -> Vec<Action> {
 }
 
 pub fn categories() -> Vec<String> {
-    ["cue", "view", "beat", "show", "reload", "select", "project"]
+    [
+        "edit", "cue", "view", "beat", "show", "reload", "select", "project", "transport",
+    ]
         .iter()
         .map(|&s| s.to_string())
         .collect()
@@ -185,6 +198,14 @@ pub fn categories() -> Vec<String> {
 
 pub fn actions(category_id: &str) -> Vec<String> {
     match category_id {
+        "edit" => vec![
+            "edit:undo",
+            "edit:redo",
+            "edit:cut",
+            "edit:copy",
+            "edit:paste",
+            "edit:paste_events",
+        ],
         "cue" => vec![
             "cue:add_beat",
             "cue:add_downbeat",
@@ -193,12 +214,15 @@ pub fn actions(category_id: &str) -> Vec<String> {
             "cue:delete_measure",
             "cue:reorder",
             "cue:add_ci_measure",
+            "cue:set_meter",
         ],
         "view" => vec![
             "view:zoom_in",
             "view:zoom_default",
             "view:zoom_out",
             "view:toggle_proportional_beat_length",
+            "view:toggle_performance_view",
+            "view:toggle_log",
         ],
         "beat" => vec![
             "beat:add_tempo_event",
@@ -219,8 +243,13 @@ pub fn actions(category_id: &str) -> Vec<String> {
             "show:duplicate_cue",
             "show:move_cue_up",
             "show:move_cue_down",
+            "show:plan_timecode",
+        ],
+        "reload" => vec![
+            "cue:recalculate_tempo_changes",
+            "cue:apply_tempo_map",
+            "show:refresh_audio_clips",
         ],
-        "reload" => vec!["cue:recalculate_tempo_changes", "show:refresh_audio_clips"],
         "select" => vec![
             "select:next_cue",
             "select:previous_cue",
@@ -230,6 +259,8 @@ pub fn actions(category_id: &str) -> Vec<String> {
             "select:previous_beat",
             "select:next_event",
             "select:previous_event",
+            "select:all_beats",
+            "select:measures",
         ],
         "project" => vec![
             "project:save_file",
@@ -237,7 +268,15 @@ pub fn actions(category_id: &str) -> Vec<String> {
             "project:load_file",
             "project:import_json",
             "project:export_json",
+            "project:import_midi",
+            "project:export_midi_cue",
+            "project:export_midi_show",
+            "project:export_click_cue",
+            "project:export_click_show",
+            "project:export_ltc_cue",
+            "project:export_ltc_show",
         ],
+        "transport" => vec!["transport:play", "transport:stop"],
         _ => vec![],
     }
     .iter()
@@ -247,6 +286,95 @@ pub fn actions(category_id: &str) -> Vec<String> {
 
 pub fn action(action_id: &str) -> Action {
     match action_id {
+        "edit:undo" => Action {
+            symbol: '<',
+            name_global: "Undo".to_string(),
+            name_concise: "Undo".to_string(),
+            icon: egui_material_icons::icons::ICON_UNDO.to_string(),
+            function: |app| {
+                app.history.undo(&mut app.project_file);
+                app.selected_cue_idx = app
+                    .selected_cue_idx
+                    .min(app.project_file.show.cues.len().saturating_sub(1));
+            },
+            interactible: |app| app.history.can_undo(),
+            active: |app| false,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::CTRL,
+                logical_key: Key::Z,
+            }),
+        },
+        "edit:redo" => Action {
+            symbol: '>',
+            name_global: "Redo".to_string(),
+            name_concise: "Redo".to_string(),
+            icon: egui_material_icons::icons::ICON_REDO.to_string(),
+            function: |app| {
+                app.history.redo(&mut app.project_file);
+                app.selected_cue_idx = app
+                    .selected_cue_idx
+                    .min(app.project_file.show.cues.len().saturating_sub(1));
+            },
+            interactible: |app| app.history.can_redo(),
+            active: |app| false,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::CTRL | Modifiers::SHIFT,
+                logical_key: Key::Z,
+            }),
+        },
+        // Ctrl+X/C/V reach the app as clipboard events rather than key presses, so these are
+        // run from check_hotkeys instead of having a hotkey of their own
+        "edit:cut" => Action {
+            symbol: 'x',
+            name_global: "Cut beats".to_string(),
+            name_concise: "Cut".to_string(),
+            icon: egui_material_icons::icons::ICON_CONTENT_CUT.to_string(),
+            function: |app| {
+                crate::clipboard::copy(app);
+                (action("cue:delete_beat").function)(app);
+            },
+            interactible: |app| has_beat!(app),
+            active: |app| false,
+            hotkey: None,
+        },
+        "edit:copy" => Action {
+            symbol: 'c',
+            name_global: "Copy beats".to_string(),
+            name_concise: "Copy".to_string(),
+            icon: egui_material_icons::icons::ICON_CONTENT_COPY.to_string(),
+            function: |app| {
+                crate::clipboard::copy(app);
+            },
+            interactible: |app| has_beat!(app),
+            active: |app| false,
+            hotkey: None,
+        },
+        "edit:paste" => Action {
+            symbol: 'v',
+            name_global: "Paste beats".to_string(),
+            name_concise: "Paste".to_string(),
+            icon: egui_material_icons::icons::ICON_CONTENT_PASTE.to_string(),
+            function: |app| {
+                app.paste_events_only = false;
+                app.ctx.send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+            },
+            interactible: |app| has_cue!(app),
+            active: |app| false,
+            hotkey: None,
+        },
+        "edit:paste_events" => Action {
+            symbol: 'v',
+            name_global: "Paste events only".to_string(),
+            name_concise: "Paste events".to_string(),
+            icon: egui_material_icons::icons::ICON_CONTENT_PASTE_GO.to_string(),
+            function: |app| {
+                app.paste_events_only = true;
+                app.ctx.send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+            },
+            interactible: |app| has_beat!(app),
+            active: |app| false,
+            hotkey: None,
+        },
         "cue:add_beat" => Action {
             symbol: '+',
             name_global: "Add beat".to_string(),
@@ -341,12 +469,13 @@ pub fn action(action_id: &str) -> Action {
         },
         "cue:delete_beat" => Action {
             symbol: '+',
-            name_global: "Delete beat".to_string(),
+            name_global: "Delete selected beats".to_string(),
             name_concise: "Delete".to_string(),
             icon: egui_material_icons::icons::ICON_CLOSE.to_string(),
             function: |app| {
-                cue_mut!(app).beats.remove(app.selected_beat_idx);
-                app.selected_beat_idx = app.selected_beat_idx.saturating_sub(1);
+                let range = app.selected_range();
+                cueutils::remove_beats(&mut cue_mut!(app), *range.start()..*range.end() + 1);
+                app.select_beat(range.start().saturating_sub(1), false);
                 cue_mut!(app).reorder_numbers();
             },
             interactible: |app| has_beat!(app),
@@ -358,20 +487,13 @@ pub fn action(action_id: &str) -> Action {
         },
         "cue:delete_measure" => Action {
             symbol: '+',
-            name_global: "Delete measure".to_string(),
+            name_global: "Delete selected measures".to_string(),
             name_concise: "Delete".to_string(),
             icon: egui_material_icons::icons::ICON_REMOVE_FROM_QUEUE.to_string(),
             function: |app| {
-                let beat = beat!(app);
-                let len_pre = cue!(app).beats.len();
-                let mut beat_vec = cue!(app)
-                    .beats
-                    .iter()
-                    .filter(|b| b.bar_number != beat.bar_number)
-                    .cloned()
-                    .collect::<Vec<Beat>>();
-                app.selected_beat_idx -= len_pre - cue!(app).beats.len();
-                cue_mut!(app).beats = beat_vec;
+                let range = cueutils::measure_range(&cue!(app), app.selected_range());
+                cueutils::remove_beats(&mut cue_mut!(app), range.clone());
+                app.select_beat(range.start.saturating_sub(1), false);
                 cue_mut!(app).reorder_numbers();
             },
             interactible: |app| has_beat!(app),
@@ -429,6 +551,27 @@ pub fn action(action_id: &str) -> Action {
                 logical_key: Key::I,
             }),
         },
+        "cue:set_meter" => Action {
+            symbol: '+',
+            name_global: "Set time signature of selected measures".to_string(),
+            name_concise: "Meter".to_string(),
+            icon: egui_material_icons::icons::ICON_MUSIC_NOTE.to_string(),
+            function: |app| {
+                // Start from the bar the selection begins in, taking its beats as quarter notes
+                let cue = cue!(app);
+                let first = cue.beats[cueutils::measure_range(&cue, app.selected_range()).start].clone();
+                app.meter_prompt = Some(MeterPrompt {
+                    meter: Meter::new(bar_length!(app, first.bar_number) as u8, 4),
+                    quarter_bpm: 60_000_000.0 / first.length.max(1) as f32,
+                });
+            },
+            interactible: |app| has_beat!(app),
+            active: |app| false,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::SHIFT,
+                logical_key: Key::T,
+            }),
+        },
         "cue:recalculate_tempo_changes" => Action {
             symbol: 'v',
             name_global: "Recalculate tempo changes".to_string(),
@@ -444,6 +587,32 @@ pub fn action(action_id: &str) -> Action {
                 logical_key: Key::R,
             }),
         },
+        "cue:apply_tempo_map" => Action {
+            symbol: 'v',
+            name_global: "Apply tempo map".to_string(),
+            name_concise: "Tempo map".to_string(),
+            icon: egui_material_icons::icons::ICON_SPEED.to_string(),
+            function: |app| {
+                let cue = cue!(app);
+                let mut map = TempoMap::new(&cue);
+                map.ramp = app.tempo_ramp;
+                map.unit = app.tempo_unit;
+                map.set_meters(&cue, &app.project_file.meters(app.selected_cue_idx));
+                for conflict in map.apply(&mut cue_mut!(app)) {
+                    app.notifications.push(
+                        Level::Warning,
+                        "Tempo conflict",
+                        format!("Beat {}: {}", conflict.beat_idx, conflict.message),
+                    );
+                }
+            },
+            interactible: |app| has_beat!(app),
+            active: |app| false,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::CTRL | Modifiers::SHIFT,
+                logical_key: Key::R,
+            }),
+        },
         "view:zoom_in" => Action {
             symbol: '+',
             name_global: "Zoom in".to_string(),
@@ -501,14 +670,55 @@ pub fn action(action_id: &str) -> Action {
                 logical_key: Key::Period,
             }),
         },
+        "view:toggle_performance_view" => Action {
+            symbol: '+',
+            name_global: "Toggle performance view".to_string(),
+            name_concise: "Performance".to_string(),
+            icon: egui_material_icons::icons::ICON_VIEW_TIMELINE.to_string(),
+            function: |app| {
+                app.performance_view = !app.performance_view;
+            },
+            interactible: |app| true,
+            active: |app| app.performance_view,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::CTRL,
+                logical_key: Key::U,
+            }),
+        },
+        "view:toggle_log" => Action {
+            symbol: 'l',
+            name_global: "Toggle message log".to_string(),
+            name_concise: "Log".to_string(),
+            icon: egui_material_icons::icons::ICON_NOTES.to_string(),
+            function: |app| {
+                app.notifications.show_log = !app.notifications.show_log;
+            },
+            interactible: |app| true,
+            active: |app| app.notifications.show_log,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::CTRL,
+                logical_key: Key::L,
+            }),
+        },
         "beat:add_tempo_event" => Action {
             symbol: '+',
             name_global: "Add tempo change".to_string(),
             name_concise: "Add".to_string(),
             icon: egui_material_icons::icons::ICON_TIMER.to_string(),
             function: |app| {
+                // A range gets a tempo of its own, going back to the old tempo after it
+                let range = app.selected_range();
+                if app.has_range()
+                    && let Some(after) = cue!(app).beats.get(range.end() + 1)
+                {
+                    let tempo = after.tempo();
+                    cue_mut!(app).events.push(Event::new(
+                        (range.end() + 1) as u16,
+                        EventDescription::TempoChangeEvent { tempo },
+                    ));
+                }
                 cue_mut!(app).events.push(Event::new(
-                    app.selected_beat_idx as u16,
+                    *range.start() as u16,
                     EventDescription::TempoChangeEvent { tempo: 120 },
                 ));
                 cue_mut!(app).recalculate_tempo_changes();
@@ -523,13 +733,18 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Add".to_string(),
             icon: "\u{f377}".to_string(),
             function: |app| {
-                let tempo = beat!(app).tempo();
+                let range = app.selected_range();
+                let tempo = cue!(app).beats[*range.start()].tempo();
                 cue_mut!(app).events.push(Event::new(
-                    app.selected_beat_idx as u16,
+                    *range.start() as u16,
                     EventDescription::GradualTempoChangeEvent {
                         start_tempo: tempo,
                         end_tempo: 120,
-                        length: 4,
+                        length: if app.has_range() {
+                            (range.end() - range.start() + 1) as _
+                        } else {
+                            4
+                        },
                     },
                 ));
             },
@@ -563,7 +778,7 @@ pub fn action(action_id: &str) -> Action {
                 cue_mut!(app).events.push(Event::new(
                     app.selected_beat_idx as u16,
                     EventDescription::TimecodeEvent {
-                        time: TimecodeInstant::new(25),
+                        time: Timecode::from_hmsf(0, 0, 0, 0, app.frame_rate).to_instant(),
                     },
                 ));
             },
@@ -592,10 +807,12 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Add".to_string(),
             icon: egui_material_icons::icons::ICON_STEP.to_string(),
             function: |app| {
+                // Over a range, jump from its last beat back to its first
+                let range = app.selected_range();
                 cue_mut!(app).events.push(Event::new(
-                    app.selected_beat_idx as u16,
+                    *range.end() as u16,
                     EventDescription::JumpEvent {
-                        destination: 0,
+                        destination: if app.has_range() { *range.start() as _ } else { 0 },
                         requirement: JumpRequirement::None,
                         when_jumped: JumpModeChange::None,
                         when_passed: JumpModeChange::None,
@@ -612,10 +829,12 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Add".to_string(),
             icon: egui_material_icons::icons::ICON_REPEAT.to_string(),
             function: |app| {
+                // Over a range, jump from its last beat back to its first
+                let range = app.selected_range();
                 cue_mut!(app).events.push(Event::new(
-                    app.selected_beat_idx as u16,
+                    *range.end() as u16,
                     EventDescription::JumpEvent {
-                        destination: 0,
+                        destination: if app.has_range() { *range.start() as _ } else { 0 },
                         requirement: JumpRequirement::JumpModeOn,
                         when_jumped: JumpModeChange::None,
                         when_passed: JumpModeChange::SetOff,
@@ -632,10 +851,12 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Add".to_string(),
             icon: egui_material_icons::icons::ICON_REPEAT_ONE.to_string(),
             function: |app| {
+                // Over a range, jump from its last beat back to its first
+                let range = app.selected_range();
                 cue_mut!(app).events.push(Event::new(
-                    app.selected_beat_idx as u16,
+                    *range.end() as u16,
                     EventDescription::JumpEvent {
-                        destination: 0,
+                        destination: if app.has_range() { *range.start() as _ } else { 0 },
                         requirement: JumpRequirement::JumpModeOn,
                         when_jumped: JumpModeChange::SetOff,
                         when_passed: JumpModeChange::SetOn,
@@ -652,17 +873,31 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Add".to_string(),
             icon: egui_material_icons::icons::ICON_STEP_OVER.to_string(),
             function: |app| {
+                // Over a range, skip it from the beat before it, since jumps happen once the beat
+                // they are on has played
+                let range = app.selected_range();
                 cue_mut!(app).events.push(Event::new(
-                    app.selected_beat_idx as u16,
+                    if app.has_range() {
+                        (range.start() - 1) as u16
+                    } else {
+                        *range.start() as u16
+                    },
                     EventDescription::JumpEvent {
-                        destination: 0,
+                        destination: if app.has_range() {
+                            (range.end() + 1) as _
+                        } else {
+                            0
+                        },
                         requirement: JumpRequirement::JumpModeOff,
                         when_jumped: JumpModeChange::SetOn,
                         when_passed: JumpModeChange::None,
                     },
                 ));
             },
-            interactible: |app| has_beat!(app),
+            // A range from the first beat has no beat before it to skip it from
+            interactible: |app| {
+                has_beat!(app) && !(app.has_range() && *app.selected_range().start() == 0)
+            },
             active: |app| false,
             hotkey: None,
         },
@@ -723,14 +958,12 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Add".to_string(),
             icon: egui_material_icons::icons::ICON_ADD_BOX.to_string(),
             function: |app| {
-                app.project_file
-                    .show
-                    .cues
-                    .insert(app.selected_cue_idx, Cue::empty());
-                app.project_file.show.cues[app.selected_cue_idx].metadata = CueMetadata {
+                let mut cue = Cue::empty();
+                cue.metadata = CueMetadata {
                     name: StaticString::new("Unnamed cue"),
                     human_ident: StaticString::new("000"),
                 };
+                app.project_file.insert_cue(app.selected_cue_idx, cue, Meters::default());
             },
             interactible: |app| true,
             active: |app| false,
@@ -742,7 +975,7 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Delete".to_string(),
             icon: egui_material_icons::icons::ICON_DELETE.to_string(),
             function: |app| {
-                app.project_file.show.cues.remove(app.selected_cue_idx);
+                app.project_file.remove_cue(app.selected_cue_idx);
                 app.selected_cue_idx = app.selected_cue_idx.saturating_sub(1);
             },
             interactible: |app| !app.project_file.show.cues.is_empty(),
@@ -764,7 +997,8 @@ pub fn action(action_id: &str) -> Action {
                     .is_null()
                 {
                     let cue = app.project_file.show.cues[app.selected_cue_idx].clone();
-                    &mut app.project_file.show.cues.insert(app.selected_cue_idx, cue);
+                    let meters = app.project_file.meters(app.selected_cue_idx);
+                    app.project_file.insert_cue(app.selected_cue_idx, cue, meters);
                     app.selected_cue_idx += 1;
                 }
             },
@@ -781,10 +1015,7 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Move up".to_string(),
             icon: egui_material_icons::icons::ICON_MOVE_UP.to_string(),
             function: |app| {
-                app.project_file
-                    .show
-                    .cues
-                    .swap(app.selected_cue_idx, app.selected_cue_idx - 1);
+                app.project_file.swap_cues(app.selected_cue_idx, app.selected_cue_idx - 1);
                 app.selected_cue_idx -= 1;
             },
             interactible: |app| app.selected_cue_idx > 0,
@@ -800,10 +1031,7 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Move down".to_string(),
             icon: egui_material_icons::icons::ICON_MOVE_DOWN.to_string(),
             function: |app| {
-                app.project_file
-                    .show
-                    .cues
-                    .swap(app.selected_cue_idx, app.selected_cue_idx + 1);
+                app.project_file.swap_cues(app.selected_cue_idx, app.selected_cue_idx + 1);
                 app.selected_cue_idx += 1;
             },
             interactible: |app| app.selected_cue_idx + 1 < app.project_file.show.cues.len(),
@@ -813,6 +1041,18 @@ pub fn action(action_id: &str) -> Action {
                 logical_key: Key::PageDown,
             }),
         },
+        "show:plan_timecode" => Action {
+            symbol: '+',
+            name_global: "Plan timecode for the show".to_string(),
+            name_concise: "Plan timecode".to_string(),
+            icon: egui_material_icons::icons::ICON_SCHEDULE.to_string(),
+            function: |app| {
+                app.chase_prompt = Some(ChasePrompt::new(app.frame_rate));
+            },
+            interactible: |app| !app.project_file.show.cues.is_empty(),
+            active: |app| app.chase_prompt.is_some(),
+            hotkey: None,
+        },
         "show:refresh_audio_clips" => Action {
             symbol: 'v',
             name_global: "Refresh audio clips".to_string(),
@@ -914,8 +1154,8 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Next".to_string(),
             icon: egui_material_icons::icons::ICON_ARROW_RIGHT_ALT.to_string(),
             function: |app| {
-                app.selected_beat_idx += 1;
-                app.selected_beat_idx = app.selected_beat_idx.max(0).min(cue!(app).beats.len() - 1);
+                let idx = (app.selected_beat_idx + 1).min(cue!(app).beats.len() - 1);
+                app.select_beat(idx, false);
             },
             interactible: |app| has_cue!(app),
             active: |app| false,
@@ -931,9 +1171,8 @@ pub fn action(action_id: &str) -> Action {
             icon: egui_material_icons::icons::ICON_ARROW_RIGHT_ALT.to_string(),
             function: |app| {
                 if app.selected_beat_idx > 0 {
-                    app.selected_beat_idx -= 1;
-                    app.selected_beat_idx =
-                        app.selected_beat_idx.max(0).min(cue!(app).beats.len() - 1);
+                    let idx = (app.selected_beat_idx - 1).min(cue!(app).beats.len() - 1);
+                    app.select_beat(idx, false);
                 }
             },
             interactible: |app| has_cue!(app),
@@ -943,16 +1182,51 @@ pub fn action(action_id: &str) -> Action {
                 logical_key: Key::ArrowLeft,
             }),
         },
+        "select:all_beats" => Action {
+            symbol: 'v',
+            name_global: "Select all beats".to_string(),
+            name_concise: "All".to_string(),
+            icon: egui_material_icons::icons::ICON_SELECT_ALL.to_string(),
+            function: |app| {
+                app.select_beat(0, false);
+                let last = cue!(app).beats.len().saturating_sub(1);
+                app.select_beat(last, true);
+            },
+            interactible: |app| has_beat!(app),
+            active: |app| false,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::CTRL,
+                logical_key: Key::A,
+            }),
+        },
+        "select:measures" => Action {
+            symbol: 'v',
+            name_global: "Select whole measures".to_string(),
+            name_concise: "Measures".to_string(),
+            icon: egui_material_icons::icons::ICON_SELECT.to_string(),
+            function: |app| {
+                let range = cueutils::measure_range(&cue!(app), app.selected_range());
+                app.select_beat(range.start, false);
+                app.select_beat(range.end - 1, true);
+            },
+            interactible: |app| has_beat!(app),
+            active: |app| false,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::CTRL | Modifiers::SHIFT,
+                logical_key: Key::A,
+            }),
+        },
         "project:load_file" => Action {
             symbol: 'v',
             name_global: "Load file".to_string(),
             name_concise: "Load".to_string(),
             icon: egui_material_icons::icons::ICON_FOLDER_OPEN.to_string(),
             function: |app| {
-                if let Some(dir) = crate::io::pick_dir() {
-                    let _ = app.project_file.load(dir);
-                    action("show:refresh_audio_clips").run(app);  
+                if app.history.is_dirty() {
+                    app.unsaved_prompt = Some(UnsavedContinuation::Load);
+                    return;
                 }
+                crate::io::load(app);
             },
             interactible: |app| true,
             active: |app| false,
@@ -967,17 +1241,7 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Save".to_string(),
             icon: egui_material_icons::icons::ICON_SAVE.to_string(),
             function: |app| {
-                if app
-                    .project_file
-                    .path
-                    .to_str()
-                    .expect("No stupid paths pls")
-                    .is_empty()
-                {
-                    action("project:save_file_as").run(app);
-                } else {
-                    let _ = app.project_file.save();
-                }
+                crate::io::save(app);
                 app.last_action = None;
             },
             interactible: |app| true,
@@ -993,9 +1257,7 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Save as".to_string(),
             icon: egui_material_icons::icons::ICON_SAVE_AS.to_string(),
             function: |app| {
-                if let Some(dir) = crate::io::pick_dir() {
-                    let _ = app.project_file.save_as(dir);
-                }
+                crate::io::save_as(app);
                 app.last_action = None;
             },
             interactible: |app| true,
@@ -1011,13 +1273,11 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Import".to_string(),
             icon: egui_material_icons::icons::ICON_FILE_OPEN.to_string(),
             function: |app| {
-    if let Some(dir) = crate::io::pick_file() && let Err(err) = app.project_file.import_json(dir.clone()) {
-        crate::io::show_dialog(
-            rfd::MessageLevel::Error,
-            "Import failed".to_string(),
-            err.to_string(),
-        );
-    }
+                if app.history.is_dirty() {
+                    app.unsaved_prompt = Some(UnsavedContinuation::ImportJson);
+                    return;
+                }
+                crate::io::import_json(app);
             },
             interactible: |app| true,
             active: |app| false,
@@ -1029,18 +1289,172 @@ pub fn action(action_id: &str) -> Action {
             name_concise: "Export".to_string(),
             icon: egui_material_icons::icons::ICON_FILE_SAVE.to_string(),
             function: |app| {
-    if let Some(dir) = crate::io::save_file() && let Err(err) = app.project_file.export_json(dir.clone()) {
-        crate::io::show_dialog(
-            rfd::MessageLevel::Error,
-            "Export failed".to_string(),
-            err.to_string(),
-        );
-    }
+                crate::io::export_json(app);
             },
             interactible: |app| true,
             active: |app| false,
             hotkey: None,
         },
+        "project:import_midi" => Action {
+            symbol: 'v',
+            name_global: "Import cue from MIDI file".to_string(),
+            name_concise: "Import MIDI".to_string(),
+            icon: egui_material_icons::icons::ICON_PIANO.to_string(),
+            function: |app| {
+                let Some(path) = crate::io::pick_file() else {
+                    return;
+                };
+                match crate::midi::import_cue(path) {
+                    Ok((cue, meters)) => {
+                        let idx = (app.selected_cue_idx + 1).min(app.project_file.show.cues.len());
+                        app.project_file.insert_cue(idx, cue, meters);
+                        app.selected_cue_idx = idx;
+                        app.selected_beat_idx = 0;
+                    }
+                    Err(err) => app.notifications.error("Import failed", err),
+                }
+            },
+            interactible: |app| true,
+            active: |app| false,
+            hotkey: None,
+        },
+        "project:export_midi_cue" => Action {
+            symbol: 'v',
+            name_global: "Export cue as MIDI file".to_string(),
+            name_concise: "Export MIDI".to_string(),
+            icon: egui_material_icons::icons::ICON_PIANO.to_string(),
+            function: |app| {
+                crate::io::export_midi(app, app.selected_cue_idx..app.selected_cue_idx + 1);
+            },
+            interactible: |app| has_cue!(app),
+            active: |app| false,
+            hotkey: None,
+        },
+        "project:export_midi_show" => Action {
+            symbol: 'v',
+            name_global: "Export show as MIDI file".to_string(),
+            name_concise: "Export MIDI".to_string(),
+            icon: egui_material_icons::icons::ICON_PIANO.to_string(),
+            function: |app| {
+                crate::io::export_midi(app, 0..app.project_file.show.cues.len());
+            },
+            interactible: |app| !app.project_file.show.cues.is_empty(),
+            active: |app| false,
+            hotkey: None,
+        },
+        "project:export_click_cue" => Action {
+            symbol: 'v',
+            name_global: "Export cue click track".to_string(),
+            name_concise: "Export click".to_string(),
+            icon: egui_material_icons::icons::ICON_AUDIO_FILE.to_string(),
+            function: |app| {
+                let renderer = ClickRenderer::default();
+                let buf = renderer.render_cue(&cue!(app));
+                if let Some(path) = crate::io::save_file()
+                    && let Err(err) = renderer.write_wav(path, &buf)
+                {
+                    app.notifications.error("Export failed", err);
+                }
+            },
+            interactible: |app| has_cue!(app),
+            active: |app| false,
+            hotkey: None,
+        },
+        "project:export_click_show" => Action {
+            symbol: 'v',
+            name_global: "Export show click track".to_string(),
+            name_concise: "Export click".to_string(),
+            icon: egui_material_icons::icons::ICON_AUDIO_FILE.to_string(),
+            function: |app| {
+                let renderer = ClickRenderer::default();
+                let buf = renderer.render_show(&app.project_file.show);
+                if let Some(path) = crate::io::save_file()
+                    && let Err(err) = renderer.write_wav(path, &buf)
+                {
+                    app.notifications.error("Export failed", err);
+                }
+            },
+            interactible: |app| !app.project_file.show.cues.is_empty(),
+            active: |app| false,
+            hotkey: None,
+        },
+        "project:export_ltc_cue" => Action {
+            symbol: 'v',
+            name_global: "Export cue timecode as LTC audio".to_string(),
+            name_concise: "Export LTC".to_string(),
+            icon: egui_material_icons::icons::ICON_AUDIO_FILE.to_string(),
+            function: |app| {
+                let renderer = LtcRenderer::default();
+                let buf = renderer.render_cue(&cue!(app));
+                if let Some(path) = crate::io::save_file()
+                    && let Err(err) = renderer.write_wav(path, &buf)
+                {
+                    app.notifications.error("Export failed", err);
+                }
+            },
+            interactible: |app| has_cue!(app),
+            active: |app| false,
+            hotkey: None,
+        },
+        "project:export_ltc_show" => Action {
+            symbol: 'v',
+            name_global: "Export show timecode as LTC audio".to_string(),
+            name_concise: "Export LTC".to_string(),
+            icon: egui_material_icons::icons::ICON_AUDIO_FILE.to_string(),
+            function: |app| {
+                let renderer = LtcRenderer::default();
+                let buf = renderer.render_show(&app.project_file.show);
+                if let Some(path) = crate::io::save_file()
+                    && let Err(err) = renderer.write_wav(path, &buf)
+                {
+                    app.notifications.error("Export failed", err);
+                }
+            },
+            interactible: |app| !app.project_file.show.cues.is_empty(),
+            active: |app| false,
+            hotkey: None,
+        },
+        "transport:play" => Action {
+            symbol: '>',
+            name_global: "Play/pause preview".to_string(),
+            name_concise: "Play".to_string(),
+            icon: egui_material_icons::icons::ICON_PLAY_PAUSE.to_string(),
+            function: |app| {
+                if let Some(transport) = &app.preview.transport
+                    && app.preview.cue_idx == app.selected_cue_idx
+                    && transport.position() < transport.len()
+                {
+                    transport.set_playing(!transport.is_playing());
+                    return;
+                }
+                let start = cue!(app).beats[0..app.selected_beat_idx.min(cue!(app).beats.len())]
+                    .iter()
+                    .map(|b| b.length as u64)
+                    .sum();
+                app.preview.play(&cue!(app), app.selected_cue_idx, &app.clip_manager, start);
+            },
+            interactible: |app| has_cue!(app),
+            active: |app| app.preview.is_playing(),
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::NONE,
+                logical_key: Key::Space,
+            }),
+        },
+        "transport:stop" => Action {
+            symbol: 's',
+            name_global: "Stop preview".to_string(),
+            name_concise: "Stop".to_string(),
+            icon: egui_material_icons::icons::ICON_STOP.to_string(),
+            function: |app| {
+                app.preview.stop();
+            },
+            interactible: |app| app.preview.transport.is_some() || app.preview.is_rendering(),
+            active: |app| false,
+            hotkey: Some(KeyboardShortcut {
+                modifiers: Modifiers::NONE,
+                logical_key: Key::Escape,
+            }),
+        },
         //"select:next_event" => Action {
         //    symbol: 'v',
         //    name_global: "Select next cue".to_string(),
@@ -1091,3 +1505,74 @@ pub fn action(action_id: &str) -> Action {
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cueutils::testing, simulate::Simulation, tempomap::TempoUnit};
+
+    fn select(app: &mut ClicksEditorApp, range: std::ops::RangeInclusive<usize>) {
+        app.select_beat(*range.start(), false);
+        app.select_beat(*range.end(), true);
+    }
+
+    #[test]
+    fn test_add_volta() {
+        let mut app = ClicksEditorApp::default();
+        app.project_file.show.cues.push(testing::cue(8));
+
+        // Repeat the first six beats, with the last two of them as the first ending
+        select(&mut app, 0..=5);
+        action("beat:add_repeat").run(&mut app);
+        select(&mut app, 4..=5);
+        action("beat:add_volta").run(&mut app);
+        let run = Simulation::default().run(&cue!(app));
+        assert_eq!(run.beats(), [0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 6, 7]);
+
+        select(&mut app, 0..=1);
+        assert!(!(action("beat:add_volta").interactible)(&app));
+    }
+
+    #[test]
+    fn test_meters_follow_cues() {
+        // Two new cues, which share the default identifier
+        let mut app = ClicksEditorApp::default();
+        action("show:add_cue").run(&mut app);
+        action("show:add_cue").run(&mut app);
+        app.project_file.meters_mut(1).set_from(1, Meter::new(6, 8));
+        assert_eq!(app.project_file.meters(0).at(1), None);
+
+        // Moving a cue takes its time signatures along
+        app.selected_cue_idx = 1;
+        action("show:move_cue_up").run(&mut app);
+        assert_eq!(app.project_file.meters(0).at(1), Some(Meter::new(6, 8)));
+        assert_eq!(app.project_file.meters(1).at(1), None);
+
+        // Setting time signatures is an undo step like any other edit
+        action("edit:undo").run(&mut app);
+        assert_eq!(app.project_file.meters(1).at(1), Some(Meter::new(6, 8)));
+        action("edit:undo").run(&mut app);
+        assert_eq!(app.project_file.meters(1).at(1), None);
+    }
+
+    #[test]
+    fn test_apply_tempo_map_in_meter() {
+        // Two bars of 6/8, clicking on the dotted quarters
+        let mut app = ClicksEditorApp::default();
+        let mut cue = testing::cue_in_bars(&[(2, 500000); 2]);
+        cue.events
+            .push(Event::new(0, EventDescription::TempoChangeEvent { tempo: 80 }));
+        app.project_file.show.cues.push(cue);
+        app.project_file.meters_mut(0).set(1..=2, Meter::new(6, 8));
+
+        app.tempo_unit = TempoUnit::DottedQuarter;
+        action("cue:apply_tempo_map").run(&mut app);
+        let lengths: Vec<u32> = cue!(app).beats.iter().map(|b| b.length).collect();
+        assert_eq!(lengths, [750000; 4]);
+
+        app.tempo_unit = TempoUnit::Quarter;
+        action("cue:apply_tempo_map").run(&mut app);
+        let lengths: Vec<u32> = cue!(app).beats.iter().map(|b| b.length).collect();
+        assert_eq!(lengths, [1125000; 4]);
+    }
+}
@@ -1,8 +1,37 @@
-use crate::{actions::{self, Action}, clip::ClipManager};
-use common::cue::Show;
+use crate::{
+    actions::{self, Action},
+    clip::ClipManager,
+    error::ProjectError,
+    history::History,
+    meter::Meters,
+    notify::Notifications,
+    panel::{
+        chase::ChasePrompt, cliplist::ClipImport, meter::MeterPrompt,
+        unsaved::UnsavedContinuation,
+    },
+    preview::Preview,
+    recovery::{self, Autosave},
+    tempomap::{Ramp, TempoUnit},
+    timecode::{BeatTimesCache, FrameRate},
+};
+use common::cue::{Cue, Show};
 use egui::{Context, FontFamily};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, default, path::PathBuf};
+use std::{
+    collections::HashMap,
+    default,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// Name of the app, which is also the name of its storage directory.
+pub const APP_ID: &str = "eframe template";
+
+/// File in the show directory with the version of common that show.bin was written with.
+const VERSION_FILE: &str = "version";
+
+/// Storage key of the undo history depth, which is kept apart from the rest of the app state.
+const HISTORY_DEPTH_KEY: &str = "history_depth";
 
 #[derive(Serialize, Deserialize)]
 #[serde(default)]
@@ -12,13 +41,52 @@ pub struct ClicksEditorApp {
     pub ctx: Context,
     pub selected_cue_idx: usize,
     pub selected_beat_idx: usize,
+    /// Cue and beat where a range selection was started, the range runs from there to the
+    /// selected beat
+    #[serde(skip)]
+    pub selection_anchor: Option<(usize, usize)>,
     pub zoom: f32,
     pub proportional_beat_length: bool,
+    /// Draw the cue the way it will be played instead of beat by beat
+    pub performance_view: bool,
     pub left_display_select: DisplaySelect,
     #[serde(skip)]
     pub clip_manager: ClipManager,
     #[serde(skip)]
     pub last_action: Option<Action>,
+    pub history: History,
+    #[serde(skip)]
+    pub preview: Preview,
+    #[serde(skip)]
+    pub unsaved_prompt: Option<UnsavedContinuation>,
+    #[serde(skip)]
+    pub close_confirmed: bool,
+    #[serde(skip)]
+    window_title: String,
+    /// Whether a click, drag or key press has ended since the show was last compared with the
+    /// history
+    #[serde(skip)]
+    interaction_ended: bool,
+    pub autosave: Autosave,
+    pub tempo_ramp: Ramp,
+    pub tempo_unit: TempoUnit,
+    /// Frame rate new timecode events start out with
+    pub frame_rate: FrameRate,
+    #[serde(skip)]
+    pub recovery_prompt: Option<PathBuf>,
+    #[serde(skip)]
+    pub notifications: Notifications,
+    /// Whether the next paste from the clipboard should only paste events
+    #[serde(skip)]
+    pub paste_events_only: bool,
+    #[serde(skip)]
+    pub meter_prompt: Option<MeterPrompt>,
+    #[serde(skip)]
+    pub clip_import: Option<ClipImport>,
+    #[serde(skip)]
+    pub chase_prompt: Option<ChasePrompt>,
+    #[serde(skip)]
+    pub beat_times: BeatTimesCache,
 }
 
 #[derive(Default, Serialize, Deserialize, Debug)]
@@ -27,6 +95,10 @@ pub struct ProjectFile {
     pub path: PathBuf,
     #[serde(skip)]
     pub show: Show,
+    /// Time signatures of the cues, in the same order as the cues of the show. Saved to
+    /// meters.json beside show.bin
+    #[serde(skip)]
+    pub meters: Vec<Meters>,
 }
 
 #[derive(Default, Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -35,110 +107,162 @@ pub enum DisplaySelect {
     Cues,
     Clips,
     Beats,
+    Problems,
 }
 
 impl ProjectFile {
-    pub fn save(&mut self) -> Result<(), std::io::Error> {
-        if !self.path.try_exists()? {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidFilename,
-                format!("'{:?}' is not a valid save path", self.path),
-            ));
+    pub fn meters(&self, cue_idx: usize) -> Meters {
+        self.meters.get(cue_idx).cloned().unwrap_or_default()
+    }
+
+    pub fn meters_mut(&mut self, cue_idx: usize) -> &mut Meters {
+        self.fit_meters();
+        &mut self.meters[cue_idx]
+    }
+
+    /// Make sure every cue has time signatures, even if none are known.
+    fn fit_meters(&mut self) {
+        self.meters
+            .resize_with(self.show.cues.len(), Meters::default);
+    }
+
+    /// Insert a cue with its time signatures before cue `idx`. Cues are added, removed and
+    /// moved through these, so their time signatures stay with them.
+    pub fn insert_cue(&mut self, idx: usize, cue: Cue, meters: Meters) {
+        self.fit_meters();
+        self.show.cues.insert(idx, cue);
+        self.meters.insert(idx, meters);
+    }
+
+    pub fn remove_cue(&mut self, idx: usize) -> (Cue, Meters) {
+        self.fit_meters();
+        (self.show.cues.remove(idx), self.meters.remove(idx))
+    }
+
+    pub fn swap_cues(&mut self, a: usize, b: usize) {
+        self.fit_meters();
+        self.show.cues.swap(a, b);
+        self.meters.swap(a, b);
+    }
+
+    /// The show and the time signatures of its cues, postcard encoded. Undo steps and recovery
+    /// snapshots are made of these.
+    pub fn snapshot(&self) -> Result<Vec<u8>, postcard::Error> {
+        postcard::to_stdvec(&(&self.show, &self.meters))
+    }
+
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), postcard::Error> {
+        (self.show, self.meters) = postcard::from_bytes::<(Show, Vec<Meters>)>(snapshot)?;
+        self.fit_meters();
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> Result<(), ProjectError> {
+        if !self.path.is_dir() {
+            return Err(ProjectError::MissingDirectory(self.path.clone()));
         }
         self.save_as(self.path.clone())
     }
 
-    pub fn save_as(&mut self, path: PathBuf) -> Result<(), std::io::Error> {
-        if !path
-            .parent()
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidFilename,
-                format!("'{:?}' is not a valid save path", path),
-            ))?
-            .try_exists()?
-        {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidFilename,
-                format!("'{:?}' is not a valid save path", path),
-            ));
+    pub fn save_as(&mut self, path: PathBuf) -> Result<(), ProjectError> {
+        match path.parent() {
+            Some(parent) if parent.is_dir() => {}
+            Some(parent) => return Err(ProjectError::MissingDirectory(parent.to_path_buf())),
+            None => return Err(ProjectError::MissingDirectory(path)),
         }
-        self.path = path.clone();
+        let previous = std::mem::replace(&mut self.path, path.clone());
 
         // Create clicks.show if not already there
-        if !path.try_exists()? {
-            std::fs::create_dir(&path)?;
+        if !path.is_dir() {
+            std::fs::create_dir(&path).map_err(|e| ProjectError::io(path.clone(), e))?;
         }
 
         // Serialize show into show.bin
-        let res = postcard::to_stdvec::<Show>(&self.show).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
-        std::fs::write(path.join("show.bin"), &res)?;
+        let file = path.join("show.bin");
+        let res = postcard::to_stdvec::<Show>(&self.show)
+            .map_err(|e| ProjectError::Encode(file.clone(), e))?;
+        std::fs::write(&file, &res).map_err(|e| ProjectError::io(file, e))?;
+
+        // Remember which version of the show format show.bin was written in
+        let file = path.join(VERSION_FILE);
+        std::fs::write(&file, common::VERSION).map_err(|e| ProjectError::io(file, e))?;
+
+        let file = path.join("meters.json");
+        let json =
+            serde_json::to_string(&self.meters).map_err(|e| ProjectError::Json(file.clone(), e))?;
+        std::fs::write(&file, json).map_err(|e| ProjectError::io(file, e))?;
+
+        // The snapshot of an untitled show is in the storage directory, not the new show's
+        recovery::remove(&previous);
+        recovery::remove(&path);
 
         Ok(())
     }
 
-    pub fn load(&mut self, path: PathBuf) -> Result<(), std::io::Error> {
-        if !path.try_exists()? {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidFilename,
-                format!("'{:?}' is not a valid path", path),
-            ));
+    pub fn load(&mut self, path: PathBuf) -> Result<(), ProjectError> {
+        if !path.is_dir() {
+            return Err(ProjectError::MissingDirectory(path));
         }
 
-        self.path = path.clone();
-
-        let data = &std::fs::read(path.join("show.bin"))?;
-        self.show = {
-            postcard::from_bytes(data)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+        let file = path.join("show.bin");
+        let data = &std::fs::read(&file).map_err(|e| ProjectError::io(file.clone(), e))?;
+        self.show = match postcard::take_from_bytes::<Show>(data) {
+            Ok((show, [])) => show,
+            res => {
+                // Bytes left over mean the file holds more than a show
+                let err = res.err().unwrap_or(postcard::Error::DeserializeBadEncoding);
+                return Err(decode_error(&path, file, err));
+            }
         };
+        // Shows saved before time signatures were kept have no meters.json
+        self.meters = std::fs::read_to_string(path.join("meters.json"))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        self.fit_meters();
+        self.path = path;
 
         Ok(())
     }
 
-    pub fn export_json(&mut self, path: PathBuf) -> Result<(), std::io::Error> {
-        if !path
-            .parent()
-            .ok_or(std::io::Error::new(
-                std::io::ErrorKind::InvalidFilename,
-                format!("'{:?}' is not a valid save path", path),
-            ))?
-            .try_exists()?
-        {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidFilename,
-                format!("'{:?}' is not a valid save path", path),
-            ));
+    pub fn export_json(&mut self, path: PathBuf) -> Result<(), ProjectError> {
+        match path.parent() {
+            Some(parent) if parent.is_dir() => {}
+            Some(parent) => return Err(ProjectError::MissingDirectory(parent.to_path_buf())),
+            None => return Err(ProjectError::MissingDirectory(path)),
         }
         self.path = path.clone();
 
         // Serialize show into show.json
         let res = serde_json::to_string::<Show>(&self.show)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
-        std::fs::write(path, res)?;
+            .map_err(|e| ProjectError::Json(path.clone(), e))?;
+        std::fs::write(&path, res).map_err(|e| ProjectError::io(path, e))?;
 
         Ok(())
     }
 
-    pub fn import_json(&mut self, path: PathBuf) -> Result<(), std::io::Error> {
-        if !path.try_exists()? {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidFilename,
-                format!("'{:?}' is not a valid path", path),
-            ));
-        }
-
-        self.path = path.clone();
-
-        let data = &std::fs::read_to_string(path)?;
-        self.show = {
-            serde_json::from_str(data)
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
-        };
+    pub fn import_json(&mut self, path: PathBuf) -> Result<(), ProjectError> {
+        let data = &std::fs::read_to_string(&path).map_err(|e| ProjectError::io(path.clone(), e))?;
+        self.show = serde_json::from_str(data).map_err(|e| ProjectError::Json(path.clone(), e))?;
+        self.meters.clear();
+        self.fit_meters();
+        self.path = path;
 
         Ok(())
     }
 }
 
+/// Blame a show.bin that can't be decoded on the version of common it was written with, if that
+/// is known to be another one than this editor uses.
+fn decode_error(path: &Path, file: PathBuf, err: postcard::Error) -> ProjectError {
+    match std::fs::read_to_string(path.join(VERSION_FILE)) {
+        Ok(version) if version.trim() != common::VERSION => {
+            ProjectError::VersionMismatch(file, version.trim().to_string())
+        }
+        _ => ProjectError::Decode(file, err),
+    }
+}
+
 impl Default for ClicksEditorApp {
     fn default() -> Self {
         Self {
@@ -146,11 +270,30 @@ impl Default for ClicksEditorApp {
             project_file: ProjectFile::default(),
             selected_cue_idx: 0,
             selected_beat_idx: 0,
+            selection_anchor: None,
             zoom: 10.0,
             proportional_beat_length: false,
+            performance_view: false,
             left_display_select: DisplaySelect::Cues,
             clip_manager: ClipManager::default(),
-            last_action: None
+            last_action: None,
+            history: History::default(),
+            preview: Preview::default(),
+            unsaved_prompt: None,
+            close_confirmed: false,
+            window_title: String::new(),
+            interaction_ended: false,
+            autosave: Autosave::default(),
+            tempo_ramp: Ramp::default(),
+            tempo_unit: TempoUnit::default(),
+            frame_rate: FrameRate::default(),
+            recovery_prompt: None,
+            notifications: Notifications::default(),
+            paste_events_only: false,
+            meter_prompt: None,
+            clip_import: None,
+            chase_prompt: None,
+            beat_times: BeatTimesCache::default(),
         }
     }
 }
@@ -168,10 +311,24 @@ impl ClicksEditorApp {
         } else {
             Box::new(Self::default())
         };
+        if let Some(depth) = cc
+            .storage
+            .and_then(|storage| storage.get_string(HISTORY_DEPTH_KEY))
+            .and_then(|depth| depth.parse().ok())
+        {
+            a.history.depth = depth;
+        }
         a.ctx = cc.egui_ctx.clone();
         egui_extras::install_image_loaders(&a.ctx);
         a.setup_custom_fonts(&a.ctx);
-        let _ = a.project_file.load(a.project_file.path.clone());
+        if let Err(err) = a.project_file.load(a.project_file.path.clone())
+            && !a.project_file.path.as_os_str().is_empty()
+        {
+            a.notifications.error("Could not reopen show", err);
+        }
+        // Whatever was loaded, or the empty show if nothing was, counts as saved
+        a.history.reset(&a.project_file);
+        a.recovery_prompt = recovery::find_newer(&a.project_file);
 
         (actions::action("show:refresh_audio_clips").function)(&mut a);
 
@@ -200,6 +357,58 @@ impl ClicksEditorApp {
         ctx.set_fonts(fonts);
     }
 
+    /// Keep the selection inside the show, whatever happened to it since the last frame.
+    fn clamp_selection(&mut self) {
+        self.selected_cue_idx = self
+            .selected_cue_idx
+            .min(self.project_file.show.cues.len().saturating_sub(1));
+        if let Some(cue) = self.project_file.show.cues.get(self.selected_cue_idx) {
+            self.selected_beat_idx = self.selected_beat_idx.min(cue.beats.len().saturating_sub(1));
+            if let Some((_, anchor)) = &mut self.selection_anchor {
+                *anchor = (*anchor).min(cue.beats.len().saturating_sub(1));
+            }
+        }
+    }
+
+    /// The selected beats, which is just the selected beat unless a range has been selected.
+    pub fn selected_range(&self) -> RangeInclusive<usize> {
+        match self.selection_anchor {
+            Some((cue_idx, anchor)) if cue_idx == self.selected_cue_idx => {
+                anchor.min(self.selected_beat_idx)..=anchor.max(self.selected_beat_idx)
+            }
+            _ => self.selected_beat_idx..=self.selected_beat_idx,
+        }
+    }
+
+    pub fn has_range(&self) -> bool {
+        let range = self.selected_range();
+        range.start() != range.end()
+    }
+
+    /// Select a beat, either on its own or by extending the range from the current selection.
+    pub fn select_beat(&mut self, idx: usize, extend: bool) {
+        if !extend {
+            self.selection_anchor = None;
+        } else if !matches!(self.selection_anchor, Some((cue_idx, _)) if cue_idx == self.selected_cue_idx)
+        {
+            self.selection_anchor = Some((self.selected_cue_idx, self.selected_beat_idx));
+        }
+        self.selected_beat_idx = idx;
+    }
+
+    fn update_title(&mut self, ctx: &egui::Context) {
+        let path = self.project_file.path.to_string_lossy();
+        let title = format!(
+            "{}{} - ClicKS Editor",
+            if self.history.is_dirty() { "* " } else { "" },
+            if path.is_empty() { "Untitled" } else { &path },
+        );
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
     fn check_hotkeys(&mut self, ui: &mut egui::Ui) {
         for action in actions::all_actions() {
             if let Some(hotkey) = action.hotkey &&
@@ -211,37 +420,63 @@ impl ClicksEditorApp {
                     action.run(self)
                 }
         }
-    }
-}
 
-impl eframe::App for ClicksEditorApp {
-    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
-        return;
-        match rfd::MessageDialog::new()
-            .set_title("Unsaved changes")
-            .set_description("Would you like to save?")
-            .set_level(rfd::MessageLevel::Warning)
-            .set_buttons(rfd::MessageButtons::YesNoCancel)
-            .show()
-        {
-            rfd::MessageDialogResult::Yes => {
-                crate::io::save(self);
+        if ui.ctx().wants_keyboard_input() {
+            return;
+        }
+        let (events, shift) = ui.input(|i| (i.events.clone(), i.modifiers.shift));
+        for event in events {
+            match event {
+                egui::Event::Copy | egui::Event::Cut => {
+                    let action = actions::action(if event == egui::Event::Copy {
+                        "edit:copy"
+                    } else {
+                        "edit:cut"
+                    });
+                    if (action.interactible)(self) {
+                        action.run(self);
+                    }
+                }
+                egui::Event::Paste(text) if (actions::action("edit:paste").interactible)(self) => {
+                    // Ctrl+Shift+V pastes only the events
+                    let events_only = std::mem::take(&mut self.paste_events_only) || shift;
+                    self.history.commit(&self.project_file);
+                    crate::clipboard::paste(self, &text, events_only);
+                    self.history.commit(&self.project_file);
+                }
+                _ => {}
             }
-            //FIXME: cancel doesn't really work. oops.
-            rfd::MessageDialogResult::Cancel => self
-                .ctx
-                .send_viewport_cmd(egui::ViewportCommand::CancelClose),
-            rfd::MessageDialogResult::No => {}
-            _ => {}
         }
     }
+}
 
+impl eframe::App for ClicksEditorApp {
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         storage.set_string(eframe::APP_KEY, serde_json::to_string(&self).unwrap());
+        storage.set_string(HISTORY_DEPTH_KEY, self.history.depth.to_string());
     }
 
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if ctx.input(|i| i.viewport().close_requested()) && !self.close_confirmed {
+            // Edits still being made count as unsaved too
+            self.history.commit(&self.project_file);
+            if self.history.is_dirty() {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                self.unsaved_prompt = Some(UnsavedContinuation::Close);
+            }
+        }
+        self.clamp_selection();
+        crate::panel::unsaved::display(self, ctx);
+        crate::panel::recovery::display(self, ctx);
+        crate::panel::meter::display(self, ctx);
+        crate::panel::cliplist::import_prompt(self, ctx);
+        crate::panel::chase::display(self, ctx);
+        self.update_title(ctx);
+        if let Err(err) = self.autosave.tick(&self.project_file, self.history.is_dirty()) {
+            self.notifications.error("Autosave failed", err);
+        }
+
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             self.check_hotkeys(ui);
             crate::panel::menubar::display(self, ui);
@@ -264,6 +499,11 @@ impl eframe::App for ClicksEditorApp {
                         DisplaySelect::Beats,
                         "Beats",
                     );
+                    ui.selectable_value(
+                        &mut self.left_display_select,
+                        DisplaySelect::Problems,
+                        "Problems",
+                    );
                 });
                 match self.left_display_select {
                     DisplaySelect::Cues => {
@@ -275,6 +515,9 @@ impl eframe::App for ClicksEditorApp {
                     DisplaySelect::Beats => {
                         crate::panel::beatlist::display(self, ui);
                     }
+                    DisplaySelect::Problems => {
+                        crate::panel::problems::display(self, ui);
+                    }
                     _ => {}
                 }
             });
@@ -292,5 +535,32 @@ impl eframe::App for ClicksEditorApp {
         egui::CentralPanel::default().show(ctx, |ui| {
             crate::panel::timeline::display(self, ui);
         });
+
+        self.notifications.display(ctx);
+
+        if let Err(err) = self.preview.poll() {
+            self.notifications.error("Preview failed", err);
+        }
+        // Keep the play cursor moving, and start playing once the cue is mixed down
+        if self.preview.is_playing() || self.preview.is_rendering() {
+            ctx.request_repaint();
+        }
+        if self.clip_manager.poll() {
+            ctx.request_repaint_after(std::time::Duration::from_millis(100));
+        }
+
+        // Edits made directly in panels become one undo step once the user lets go of them, so
+        // the show is only compared with the history after an interaction that could have
+        // changed it
+        self.interaction_ended |= ctx.input(|i| {
+            i.pointer.any_released()
+                || i.events
+                    .iter()
+                    .any(|e| matches!(e, egui::Event::Key { pressed: false, .. }))
+        });
+        if self.interaction_ended && !ctx.is_using_pointer() && !ctx.wants_keyboard_input() {
+            self.interaction_ended = false;
+            self.history.commit(&self.project_file);
+        }
     }
 }
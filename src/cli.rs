@@ -0,0 +1,248 @@
+use crate::{
+    app::ProjectFile,
+    click::ClickRenderer,
+    clip::ClipManager,
+    ltc::LtcRenderer,
+    validate::{self, Severity},
+};
+use common::cue::{Cue, Show};
+use std::path::Path;
+
+const USAGE: &str = "\
+Usage: clicks-editor [<command> <args>...]
+
+Without a command, the editor GUI is started.
+
+Commands:
+  convert <input> <output>   Convert a show between clicks.show (show.bin) and JSON.
+                             Outputs ending in .json are written as JSON, anything else
+                             is written as a clicks.show directory.
+  validate <input>           Load a show and check it for errors.
+  summary <input>            Print a summary of the cues in a show.
+  click <input> <output.wav> [<cue id>]
+                             Render the click track of a cue, or of the whole show if no
+                             cue id is given, to a WAV file.
+  ltc <input> <output.wav> [<cue id>]
+                             Render the linear timecode of a cue, or of the whole show if
+                             no cue id is given, to a WAV file.
+  help                       Print this message.
+
+<input> is either a clicks.show directory or a .json file.";
+
+/// First arguments that start the editor as a command line tool instead of the GUI.
+const COMMANDS: &[&str] = &[
+    "convert", "validate", "summary", "click", "ltc", "help", "--help", "-h",
+];
+
+/// Whether the arguments are a command to run headless. Anything else, like a file the editor
+/// was opened with, starts the GUI.
+pub fn is_command(args: &[String]) -> bool {
+    args.first()
+        .is_some_and(|arg| COMMANDS.contains(&arg.as_str()))
+}
+
+/// Release builds on Windows are GUI programs, which don't get a console of their own. Write to
+/// the console of the shell the editor was started from instead, if there is one. The shell
+/// doesn't wait for GUI programs, so the output may come after its next prompt.
+#[cfg(all(windows, not(debug_assertions)))]
+fn attach_console() {
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+    // Fails when started without a console, and then there is nowhere to write to anyway
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(all(windows, not(debug_assertions))))]
+fn attach_console() {}
+
+/// Runs the editor as a command line tool, returning the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    attach_console();
+    let res = match args.iter().map(|s| s.as_str()).collect::<Vec<&str>>()[..] {
+        ["convert", input, output] => convert(Path::new(input), Path::new(output)),
+        ["validate", input] => validate(Path::new(input)),
+        ["summary", input] => summary(Path::new(input)),
+        ["click", input, output] => click(Path::new(input), Path::new(output), None),
+        ["click", input, output, cue_id] => {
+            click(Path::new(input), Path::new(output), Some(cue_id))
+        }
+        ["ltc", input, output] => ltc(Path::new(input), Path::new(output), None),
+        ["ltc", input, output, cue_id] => ltc(Path::new(input), Path::new(output), Some(cue_id)),
+        ["help"] | ["--help"] | ["-h"] => {
+            println!("{USAGE}");
+            return 0;
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            return 2;
+        }
+    };
+    match res {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("error: {err}");
+            1
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+pub fn load(path: &Path) -> Result<ProjectFile, std::io::Error> {
+    let mut project_file = ProjectFile::default();
+    if is_json(path) {
+        project_file.import_json(path.to_path_buf())?;
+    } else if path.file_name().is_some_and(|name| name == "show.bin") {
+        // Accept a path straight to the show.bin inside clicks.show as well
+        project_file.load(path.parent().unwrap_or(Path::new(".")).to_path_buf())?;
+    } else {
+        project_file.load(path.to_path_buf())?;
+    }
+    Ok(project_file)
+}
+
+fn convert(input: &Path, output: &Path) -> Result<(), std::io::Error> {
+    let mut project_file = load(input)?;
+    if is_json(output) {
+        project_file.export_json(output.to_path_buf())?;
+    } else {
+        project_file.save_as(output.to_path_buf())?;
+    }
+    println!("{} -> {}", input.display(), output.display());
+    Ok(())
+}
+
+fn validate(input: &Path) -> Result<(), std::io::Error> {
+    let project_file = load(input)?;
+
+    // The host reads show.bin, so whatever we validate has to survive the trip through postcard
+    let encoded = postcard::to_stdvec::<Show>(&project_file.show)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    let decoded = postcard::from_bytes::<Show>(&encoded)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    if postcard::to_stdvec::<Show>(&decoded).ok() != Some(encoded) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "show does not survive a round trip through show.bin",
+        ));
+    }
+
+    // Playback media only exists next to a show.bin, not for a loose JSON file
+    let mut clip_manager = ClipManager::default();
+    let clips = if is_json(input) {
+        None
+    } else {
+        let _ = clip_manager.scan(project_file.path.clone());
+        Some(&clip_manager)
+    };
+
+    let problems = validate::validate_show(&project_file.show, clips);
+    for problem in &problems {
+        let cue = &project_file.show.cues[problem.cue_idx];
+        println!(
+            "{}: cue {}{}: {}",
+            match problem.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            cue.metadata.human_ident.str(),
+            problem
+                .beat_idx
+                .map(|idx| format!(", beat {idx}"))
+                .unwrap_or_default(),
+            problem.message,
+        );
+    }
+    let errors = problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{errors} error(s) in {}", input.display()),
+        ));
+    }
+
+    println!("{}: ok", input.display());
+    Ok(())
+}
+
+fn summary(input: &Path) -> Result<(), std::io::Error> {
+    let project_file = load(input)?;
+    let show = &project_file.show;
+
+    println!("Show: {}", show.metadata.name.str());
+    println!("Cues: {}", show.cues.len());
+    println!();
+    println!(
+        "{:<6} {:<32} {:>6} {:>6} {:>7} {:>10}",
+        "Id", "Name", "Bars", "Beats", "Events", "Duration"
+    );
+    for cue in &show.cues {
+        let mut bars = cue.beats.iter().map(|b| b.bar_number).collect::<Vec<_>>();
+        bars.dedup();
+        let duration_us: u64 = cue.beats.iter().map(|b| b.length as u64).sum();
+        println!(
+            "{:<6} {:<32} {:>6} {:>6} {:>7} {:>10}",
+            cue.metadata.human_ident.str(),
+            cue.metadata.name.str(),
+            bars.len(),
+            cue.beats.len(),
+            cue.events.len(),
+            format_duration(duration_us),
+        );
+    }
+    Ok(())
+}
+
+fn find_cue<'a>(show: &'a Show, id: &str) -> Result<&'a Cue, std::io::Error> {
+    show.cues
+        .iter()
+        .find(|cue| cue.metadata.human_ident.str() == id)
+        .ok_or(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("no cue with id '{id}'"),
+        ))
+}
+
+fn click(input: &Path, output: &Path, cue_id: Option<&str>) -> Result<(), std::io::Error> {
+    let project_file = load(input)?;
+    let renderer = ClickRenderer::default();
+    let buf = match cue_id {
+        None => renderer.render_show(&project_file.show),
+        Some(id) => renderer.render_cue(find_cue(&project_file.show, id)?),
+    };
+    renderer
+        .write_wav(output.to_path_buf(), &buf)
+        .map_err(std::io::Error::other)?;
+    println!("{} -> {}", input.display(), output.display());
+    Ok(())
+}
+
+fn ltc(input: &Path, output: &Path, cue_id: Option<&str>) -> Result<(), std::io::Error> {
+    let project_file = load(input)?;
+    let renderer = LtcRenderer::default();
+    let buf = match cue_id {
+        None => renderer.render_show(&project_file.show),
+        Some(id) => renderer.render_cue(find_cue(&project_file.show, id)?),
+    };
+    renderer
+        .write_wav(output.to_path_buf(), &buf)
+        .map_err(std::io::Error::other)?;
+    println!("{} -> {}", input.display(), output.display());
+    Ok(())
+}
+
+pub fn format_duration(us: u64) -> String {
+    let secs = us / 1_000_000;
+    format!("{}:{:02}.{:03}", secs / 60, secs % 60, us / 1000 % 1000)
+}
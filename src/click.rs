@@ -0,0 +1,128 @@
+use common::{
+    beat::Beat,
+    cue::{Cue, Show},
+};
+use std::path::PathBuf;
+
+/// The sounds the metronome makes. Downbeats are accented, and count-in bars (bar 0) get a
+/// different pitch so musicians can tell them apart from the song itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClickSound {
+    Downbeat,
+    Beat,
+    CountInDownbeat,
+    CountInBeat,
+}
+
+impl ClickSound {
+    pub fn of(beat: &Beat) -> Self {
+        match (beat.bar_number == 0, beat.count == 1) {
+            (false, true) => Self::Downbeat,
+            (false, false) => Self::Beat,
+            (true, true) => Self::CountInDownbeat,
+            (true, false) => Self::CountInBeat,
+        }
+    }
+
+    /// Frequency in Hz and gain of the click.
+    fn tone(&self) -> (f32, f32) {
+        match self {
+            Self::Downbeat => (1760.0, 1.0),
+            Self::Beat => (880.0, 0.6),
+            Self::CountInDownbeat => (1320.0, 1.0),
+            Self::CountInBeat => (660.0, 0.6),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ClickRenderer {
+    pub sample_rate: u32,
+    /// Length of a single click, in seconds
+    pub click_length: f32,
+    /// Silence between cues when rendering a whole show, in microseconds
+    pub cue_gap: u64,
+    pub volume: f32,
+}
+
+impl Default for ClickRenderer {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            click_length: 0.03,
+            cue_gap: 2_000_000,
+            volume: 0.8,
+        }
+    }
+}
+
+impl ClickRenderer {
+    fn us_to_samples(&self, us: u64) -> usize {
+        (us * self.sample_rate as u64 / 1_000_000) as usize
+    }
+
+    /// Mix a single click into `buf`, starting at sample `at`.
+    pub fn click(&self, buf: &mut [f32], at: usize, sound: ClickSound) {
+        let (freq, gain) = sound.tone();
+        let len = (self.click_length * self.sample_rate as f32) as usize;
+        for i in 0..len {
+            let Some(sample) = buf.get_mut(at + i) else {
+                break;
+            };
+            let t = i as f32 / self.sample_rate as f32;
+            // Exponential decay, so the click is short and doesn't blur into the next one
+            let env = (-t / self.click_length * 5.0).exp();
+            *sample += (t * freq * std::f32::consts::TAU).sin() * env * gain * self.volume;
+        }
+    }
+
+    /// Total length of a cue, in microseconds.
+    pub fn cue_length(cue: &Cue) -> u64 {
+        cue.beats.iter().map(|b| b.length as u64).sum()
+    }
+
+    /// Render the clicks of a cue into `buf`, starting `offset` microseconds in.
+    pub fn render_cue_into(&self, cue: &Cue, buf: &mut [f32], offset: u64) {
+        let mut time_head = offset;
+        for beat in &cue.beats {
+            self.click(buf, self.us_to_samples(time_head), ClickSound::of(beat));
+            time_head += beat.length as u64;
+        }
+    }
+
+    pub fn render_cue(&self, cue: &Cue) -> Vec<f32> {
+        let mut buf = vec![0.0; self.us_to_samples(Self::cue_length(cue)) + 1];
+        self.render_cue_into(cue, &mut buf, 0);
+        buf
+    }
+
+    /// Render every cue in the show back to back, with `cue_gap` of silence in between.
+    pub fn render_show(&self, show: &Show) -> Vec<f32> {
+        let total: u64 = show
+            .cues
+            .iter()
+            .map(|cue| Self::cue_length(cue) + self.cue_gap)
+            .sum();
+        let mut buf = vec![0.0; self.us_to_samples(total) + 1];
+        let mut offset = 0;
+        for cue in &show.cues {
+            self.render_cue_into(cue, &mut buf, offset);
+            offset += Self::cue_length(cue) + self.cue_gap;
+        }
+        buf
+    }
+
+    pub fn write_wav(&self, path: PathBuf, buf: &[f32]) -> Result<(), hound::Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        for sample in buf {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()
+    }
+}
@@ -1,29 +1,164 @@
-use std::{collections::HashMap, ops::Div, path::PathBuf};
+use std::{
+    collections::HashMap,
+    ops::{Div, Range},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
+    time::UNIX_EPOCH,
+};
 
+use crate::tempodetect::{self, Detection};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ClipManager {
     pub clips: HashMap<(usize, usize), Clip>,
+    /// Clips the background workers are done with, and how many they were given
+    #[serde(skip)]
+    pub progress: (usize, usize),
+    #[serde(skip)]
+    results: Option<mpsc::Receiver<PeakResult>>,
+}
+
+/// What the clip manifest of a show says about one clip.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ManifestEntry {
+    pub channel_idx: usize,
+    pub clip_idx: usize,
+    pub name: String,
+    /// File the clip was imported from
+    pub source: String,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub notes: String,
 }
 
 impl ClipManager {
     pub const PEAK_BUCKET_SIZE: usize = 256;
+    /// Format the host plays clips in
+    pub const SAMPLE_RATE: u32 = 48000;
+    pub const BITS_PER_SAMPLE: u16 = 16;
 
-    pub fn import(&mut self, showfile: PathBuf) -> Result<(), std::io::Error> {
+    /// Clip names, notes and formats, kept next to the clips themselves.
+    pub fn manifest_path(showfile: &Path) -> PathBuf {
+        showfile.join("playback_media").join("clips.json")
+    }
+
+    /// Write the manifest for the clips in the show directory.
+    pub fn save_manifest(&self, showfile: &Path) -> Result<(), std::io::Error> {
+        let mut entries: Vec<ManifestEntry> = self
+            .clips
+            .iter()
+            .map(|((channel_idx, clip_idx), clip)| ManifestEntry {
+                channel_idx: *channel_idx,
+                clip_idx: *clip_idx,
+                name: clip.name.clone(),
+                source: clip.source.clone(),
+                sample_rate: clip.sample_rate,
+                channels: clip.channels,
+                notes: clip.notes.clone(),
+            })
+            .collect();
+        entries.sort_by_key(|e| (e.channel_idx, e.clip_idx));
+        let json = serde_json::to_string_pretty(&entries).map_err(std::io::Error::other)?;
+        std::fs::write(Self::manifest_path(showfile), json)
+    }
+
+    /// Read the manifest of the show, if it has one.
+    fn load_manifest(showfile: &Path) -> HashMap<(usize, usize), ManifestEntry> {
+        std::fs::read_to_string(Self::manifest_path(showfile))
+            .ok()
+            .and_then(|json| serde_json::from_str::<Vec<ManifestEntry>>(&json).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|e| ((e.channel_idx, e.clip_idx), e))
+            .collect()
+    }
+
+    /// Clips sorted by channel and index.
+    pub fn sorted(&self) -> Vec<(&(usize, usize), &Clip)> {
+        let mut clips: Vec<_> = self.clips.iter().collect();
+        clips.sort_by_key(|(key, _)| **key);
+        clips
+    }
+
+    /// Name of the clip in a slot, or its numbers if there is no such clip.
+    pub fn label(&self, channel_idx: usize, clip_idx: usize) -> String {
+        match self.clips.get(&(channel_idx, clip_idx)) {
+            Some(clip) => clip.label(),
+            None => format!("{channel_idx}.{clip_idx} (missing)"),
+        }
+    }
+
+    /// Where the host looks for a clip in the show directory.
+    pub fn clip_path(showfile: &Path, channel_idx: usize, clip_idx: usize) -> PathBuf {
+        showfile
+            .join("playback_media")
+            .join(channel_idx.to_string())
+            .join(format!("{clip_idx}.wav"))
+    }
+
+    /// Convert a WAV file to the format of the host, write it into the clip slot and reload the
+    /// clips. Returns the path of the new clip.
+    pub fn add_file(
+        &mut self,
+        showfile: PathBuf,
+        source: &Path,
+        channel_idx: usize,
+        clip_idx: usize,
+    ) -> Result<PathBuf, hound::Error> {
+        let (mono, spec) = Clip::read_mono(source.to_path_buf())?;
+        let samples = resample(&mono, spec.sample_rate, Self::SAMPLE_RATE);
+
+        let path = Self::clip_path(&showfile, channel_idx, clip_idx);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut writer = hound::WavWriter::create(
+            &path,
+            hound::WavSpec {
+                channels: 1,
+                sample_rate: Self::SAMPLE_RATE,
+                bits_per_sample: Self::BITS_PER_SAMPLE,
+                sample_format: hound::SampleFormat::Int,
+            },
+        )?;
+        let max = (1 << (Self::BITS_PER_SAMPLE - 1)) as f32 - 1.0;
+        for sample in samples {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * max).round() as i16)?;
+        }
+        writer.finalize()?;
+
+        self.import(showfile.clone())?;
+        if let Some(clip) = self.clips.get_mut(&(channel_idx, clip_idx)) {
+            clip.name = source
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            clip.source = source
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default();
+            clip.notes.clear();
+        }
+        self.save_manifest(&showfile)?;
+        Ok(path)
+    }
+
+    /// Find the clips of a show from the headers of their files, without decoding them.
+    pub fn scan(&mut self, showfile: PathBuf) -> Result<(), std::io::Error> {
+        self.clips.clear();
+        let mut manifest = Self::load_manifest(&showfile);
         let read_channel_dir = std::fs::read_dir(showfile.join("playback_media"))?;
         for channel_dir_res in read_channel_dir {
             let channel_dir = channel_dir_res?;
+            if !channel_dir.path().is_dir() {
+                continue;
+            }
             let read_clip_dir = std::fs::read_dir(channel_dir.path())?;
             for clip_file_res in read_clip_dir {
                 let clip_file = clip_file_res?;
 
-                let res = hound::WavReader::open(clip_file.path());
-                if res.is_err() {
-                    continue;
-                }
-                let mut reader = res.expect("Error case handled above.");
-
                 let channel_idx: usize =
                     str::parse(channel_dir.file_name().to_str().unwrap_or("nope"))
                         .unwrap_or(usize::MAX);
@@ -42,84 +177,395 @@ impl ClipManager {
                     continue;
                 }
 
-                let buf: Vec<f32> = match reader.spec().sample_format {
-                    hound::SampleFormat::Float => reader
-                        .samples::<f32>()
-                        .map(|sample| {
-                            if let Err(err) = sample {
-                                return 0.0;
-                            }
-                            return sample.expect("Err already handled.");
-                        })
-                        .collect(),
-                    hound::SampleFormat::Int => reader
-                        .samples::<i32>()
-                        .map(|sample| {
-                            if let Err(err) = sample {
-                                return 0.0;
-                            }
-                            return (sample.expect("Err already handled.") as f32).div(32768.0);
-                        })
-                        .collect(),
+                let reader = match hound::WavReader::open(clip_file.path()) {
+                    Ok(reader) => reader,
+                    Err(_) => continue,
                 };
 
-                let mut clip = Clip::new(clip_file.path(), Self::PEAK_BUCKET_SIZE);
-                clip.generate_peaks(buf);
+                let mut clip = Clip::new(clip_file.path());
+                clip.sample_rate = reader.spec().sample_rate;
+                clip.channels = reader.spec().channels;
+                clip.length = reader.duration() as usize;
+                if let Some(entry) = manifest.remove(&(channel_idx, clip_idx)) {
+                    clip.name = entry.name;
+                    clip.source = entry.source;
+                    clip.notes = entry.notes;
+                }
                 self.clips.insert((channel_idx, clip_idx), clip);
             }
         }
         Ok(())
     }
+
+    /// Find the clips of a show, and start working out their peaks and tempo in the background.
+    pub fn import(&mut self, showfile: PathBuf) -> Result<(), std::io::Error> {
+        self.scan(showfile.clone())?;
+
+        let jobs: Vec<PeakJob> = self
+            .clips
+            .iter()
+            .map(|(key, clip)| PeakJob {
+                key: *key,
+                path: clip.path.clone(),
+                cache: showfile
+                    .join(".peaks")
+                    .join(format!("{}.{}.peaks", key.0, key.1)),
+            })
+            .collect();
+        self.progress = (0, jobs.len());
+        if jobs.is_empty() {
+            self.results = None;
+            return Ok(());
+        }
+
+        // Dropping the receiver of an earlier import stops its workers after their current clip
+        let (sender, receiver) = mpsc::channel();
+        self.results = Some(receiver);
+        let jobs = Arc::new(Mutex::new(jobs));
+        let workers = std::thread::available_parallelism()
+            .map_or(2, |n| n.get())
+            .min(4);
+        for _ in 0..workers {
+            let jobs = jobs.clone();
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                loop {
+                    let Some(job) = jobs.lock().ok().and_then(|mut jobs| jobs.pop()) else {
+                        break;
+                    };
+                    if sender.send(job.run()).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Take in the peaks the workers have finished. Returns true while some are still missing.
+    pub fn poll(&mut self) -> bool {
+        let Some(results) = &self.results else {
+            return false;
+        };
+        while let Ok(result) = results.try_recv() {
+            self.progress.0 += 1;
+            if let Some(clip) = self.clips.get_mut(&result.key)
+                && clip.path == result.path
+            {
+                clip.peaks = result.peaks;
+                clip.tempo = result.tempo;
+            }
+        }
+        if self.progress.0 >= self.progress.1 {
+            self.results = None;
+        }
+        self.results.is_some()
+    }
+}
+
+/// Peaks of one clip, waiting to be worked out by a background worker.
+struct PeakJob {
+    key: (usize, usize),
+    path: PathBuf,
+    cache: PathBuf,
+}
+
+struct PeakResult {
+    key: (usize, usize),
+    path: PathBuf,
+    peaks: Option<Peaks>,
+    tempo: Option<Detection>,
+}
+
+/// What is stored in the peak cache of a clip. The cache is only used while the size and
+/// modification time of the clip file match.
+#[derive(Serialize, Deserialize)]
+struct PeakCache {
+    size: u64,
+    modified: u64,
+    peaks: Peaks,
+    tempo: Option<Detection>,
+}
+
+impl PeakJob {
+    fn run(self) -> PeakResult {
+        let (peaks, tempo) = match self.load() {
+            Some((peaks, tempo)) => (Some(peaks), tempo),
+            None => match Clip::read_samples(self.path.clone()) {
+                Ok((buf, spec)) => {
+                    let peaks = Peaks::generate(&buf, spec.channels as usize);
+                    let tempo = tempodetect::detect(&buf, spec.channels as usize, spec.sample_rate);
+                    self.store(&peaks, tempo);
+                    (Some(peaks), tempo)
+                }
+                Err(_) => (None, None),
+            },
+        };
+        PeakResult {
+            key: self.key,
+            path: self.path,
+            peaks,
+            tempo,
+        }
+    }
+
+    /// Size and modification time of the clip file
+    fn file_key(&self) -> Option<(u64, u64)> {
+        let meta = std::fs::metadata(&self.path).ok()?;
+        let modified = meta
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_secs();
+        Some((meta.len(), modified))
+    }
+
+    fn load(&self) -> Option<(Peaks, Option<Detection>)> {
+        let (size, modified) = self.file_key()?;
+        let data = std::fs::read(&self.cache).ok()?;
+        let cache: PeakCache = postcard::from_bytes(&data).ok()?;
+        (cache.size == size && cache.modified == modified).then_some((cache.peaks, cache.tempo))
+    }
+
+    /// Failing to write the cache only means working the peaks out again next time.
+    fn store(&self, peaks: &Peaks, tempo: Option<Detection>) {
+        let Some((size, modified)) = self.file_key() else {
+            return;
+        };
+        let Ok(data) = postcard::to_stdvec(&PeakCache {
+            size,
+            modified,
+            peaks: peaks.clone(),
+            tempo,
+        }) else {
+            return;
+        };
+        if let Some(dir) = self.cache.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = std::fs::write(&self.cache, data);
+    }
+}
+
+/// Lowest and highest sample of a clip over buckets of frames, at several resolutions.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Peaks {
+    /// Level 0 has a bucket for every PEAK_BUCKET_SIZE frames, and each level after it merges
+    /// LEVEL_FACTOR buckets of the one before
+    pub levels: Vec<Vec<(f32, f32)>>,
+}
+
+impl Peaks {
+    const LEVEL_FACTOR: usize = 4;
+
+    /// Work out the peaks of interleaved audio, taking all channels together.
+    pub fn generate(buf: &[f32], channels: usize) -> Self {
+        let base: Vec<(f32, f32)> = buf
+            .chunks(ClipManager::PEAK_BUCKET_SIZE * channels.max(1))
+            .map(|bucket| {
+                bucket
+                    .iter()
+                    .fold((0.0f32, 0.0f32), |(min, max), s| (min.min(*s), max.max(*s)))
+            })
+            .collect();
+        let mut levels = vec![base];
+        while let Some(last) = levels.last()
+            && last.len() > 1
+        {
+            let next = last
+                .chunks(Self::LEVEL_FACTOR)
+                .map(|buckets| {
+                    buckets.iter().fold((0.0f32, 0.0f32), |(min, max), b| {
+                        (min.min(b.0), max.max(b.1))
+                    })
+                })
+                .collect();
+            levels.push(next);
+        }
+        Self { levels }
+    }
+
+    /// Frames in each bucket of a level
+    pub fn bucket_size(level: usize) -> usize {
+        ClipManager::PEAK_BUCKET_SIZE * Self::LEVEL_FACTOR.pow(level as u32)
+    }
+
+    /// Lowest and highest sample in a range of frames, read from the coarsest level that still
+    /// has a few buckets in the range.
+    pub fn range(&self, frames: Range<usize>) -> Option<(f32, f32)> {
+        if frames.is_empty() {
+            return None;
+        }
+        let level = (0..self.levels.len())
+            .take_while(|level| Self::bucket_size(*level) <= frames.len())
+            .last()
+            .unwrap_or(0);
+        let size = Self::bucket_size(level);
+        let buckets = self.levels.get(level)?;
+        let start = frames.start / size;
+        let end = frames.end.div_ceil(size).min(buckets.len());
+        buckets
+            .get(start..end)?
+            .iter()
+            .copied()
+            .reduce(|a, b| (a.0.min(b.0), a.1.max(b.1)))
+    }
+
+    /// Loudest sample of the clip
+    pub fn max_abs(&self) -> f32 {
+        self.levels
+            .last()
+            .into_iter()
+            .flatten()
+            .map(|(min, max)| min.abs().max(max.abs()))
+            .fold(0.0, f32::max)
+    }
+}
+
+/// Average interleaved samples into one channel.
+fn mix_down(buf: &[f32], channels: usize) -> Vec<f32> {
+    let channels = channels.max(1);
+    buf.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect()
+}
+
+/// Change the sample rate of one channel of audio, interpolating between neighbouring samples.
+fn resample(buf: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || from == 0 || buf.is_empty() {
+        return buf.to_vec();
+    }
+    let step = from as f64 / to as f64;
+    let len = (buf.len() as f64 / step).floor() as usize;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * step;
+            let idx = pos as usize;
+            let frac = (pos - idx as f64) as f32;
+            let a = buf[idx.min(buf.len() - 1)];
+            let b = buf[(idx + 1).min(buf.len() - 1)];
+            a + (b - a) * frac
+        })
+        .collect()
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Clip {
-    peak_bucket_size: usize,
-    pub peak_buckets: Vec<f32>,
     pub path: PathBuf,
+    /// Number of frames in the file, which is the number of samples in each channel
     pub length: usize,
+    pub sample_rate: u32,
+    pub channels: u16,
+    /// Estimated in the background after the clip is imported
+    pub tempo: Option<Detection>,
+    pub name: String,
+    /// File the clip was imported from
+    pub source: String,
+    pub notes: String,
+    /// None until the background workers are done with the clip
+    pub peaks: Option<Peaks>,
 }
 
 impl Clip {
-    pub fn new(path: PathBuf, peak_bucket_size: usize) -> Self {
+    /// Read all samples of a WAV file as floats between -1 and 1, interleaved if the file has
+    /// more than one channel.
+    pub fn read_samples(path: PathBuf) -> Result<(Vec<f32>, hound::WavSpec), hound::Error> {
+        let mut reader = hound::WavReader::open(path)?;
+        // Integer samples of any bit depth are read into an i32 without being shifted up
+        let full_scale = (1i64 << (reader.spec().bits_per_sample.clamp(1, 32) - 1)) as f32;
+        let buf: Vec<f32> = match reader.spec().sample_format {
+            hound::SampleFormat::Float => reader
+                .samples::<f32>()
+                .map(|sample| {
+                    if let Err(err) = sample {
+                        return 0.0;
+                    }
+                    return sample.expect("Err already handled.");
+                })
+                .collect(),
+            hound::SampleFormat::Int => reader
+                .samples::<i32>()
+                .map(|sample| {
+                    if let Err(err) = sample {
+                        return 0.0;
+                    }
+                    return (sample.expect("Err already handled.") as f32).div(full_scale);
+                })
+                .collect(),
+        };
+        Ok((buf, reader.spec()))
+    }
+
+    /// Read a WAV file with all of its channels mixed into one.
+    pub fn read_mono(path: PathBuf) -> Result<(Vec<f32>, hound::WavSpec), hound::Error> {
+        let (buf, spec) = Self::read_samples(path)?;
+        Ok((mix_down(&buf, spec.channels as usize), spec))
+    }
+
+    pub fn new(path: PathBuf) -> Self {
         Self {
-            peak_bucket_size,
-            peak_buckets: vec![],
             path,
             length: 0,
+            sample_rate: 48000,
+            channels: 1,
+            tempo: None,
+            name: String::new(),
+            source: String::new(),
+            notes: String::new(),
+            peaks: None,
         }
     }
 
-    pub fn generate_peaks(&mut self, buf: Vec<f32>) {
-        self.peak_buckets.clear();
-        let mut sample_idx = 0;
-        for _ in 0..(buf.len() / self.peak_bucket_size + 1) {
-            self.peak_buckets.push(0.0);
-        }
-        while sample_idx < buf.len() {
-            let peak_bucket_idx = sample_idx / self.peak_bucket_size;
-            self.peak_buckets[peak_bucket_idx] += buf[sample_idx] / self.peak_bucket_size as f32;
-            sample_idx += 1;
+    /// Name of the clip, or its file name if it has none.
+    pub fn label(&self) -> String {
+        if !self.name.is_empty() {
+            return self.name.clone();
         }
-        self.length = sample_idx;
-        self.normalize();
-        self.inflate();
+        self.path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default()
     }
 
-    pub fn normalize(&mut self) {
-        let max_amplitude = self
-            .peak_buckets
-            .iter()
-            .max_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap())
-            .unwrap_or(&1.0);
-        self.peak_buckets = self
-            .peak_buckets
-            .iter()
-            .map(|f| f / max_amplitude)
-            .collect();
+    /// Length of the clip in seconds.
+    pub fn duration(&self) -> f64 {
+        self.length as f64 / self.sample_rate.max(1) as f64
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_wav(name: &str, bits_per_sample: u16, samples: &[i32]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut writer = hound::WavWriter::create(
+            &path,
+            hound::WavSpec {
+                channels: 1,
+                sample_rate: 48000,
+                bits_per_sample,
+                sample_format: hound::SampleFormat::Int,
+            },
+        )
+        .unwrap();
+        for sample in samples {
+            writer.write_sample(*sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_read_samples_bit_depth() {
+        let path = write_wav("clicks-editor-test-16bit.wav", 16, &[16384, -32768, 0]);
+        let (samples, _) = Clip::read_samples(path).unwrap();
+        assert_eq!(samples, [0.5, -1.0, 0.0]);
 
-    pub fn inflate(&mut self) {
-        self.peak_buckets = self.peak_buckets.iter().map(|f| f.abs().cbrt()).collect();
+        let path = write_wav("clicks-editor-test-24bit.wav", 24, &[4194304, -8388608, 0]);
+        let (samples, spec) = Clip::read_samples(path).unwrap();
+        assert_eq!(spec.bits_per_sample, 24);
+        assert_eq!(samples, [0.5, -1.0, 0.0]);
     }
 }
@@ -0,0 +1,198 @@
+use crate::{app::ClicksEditorApp, cueutils, notify::Level};
+use common::{beat::Beat, cue::Cue, event::Event};
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Tag at the top of the clipboard JSON, so text that isn't ours is never pasted as beats.
+const FORMAT: &str = "clicks-editor/beats";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClipEvent {
+    /// Located relative to the first copied beat
+    pub event: Event,
+    /// Whether the jump destination of the event is one of the copied beats, relative to the
+    /// first of them. Destinations outside the copied beats are kept as they are.
+    pub relative_destination: bool,
+}
+
+/// A range of beats with the events on them, as it travels through the system clipboard.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BeatClip {
+    format: String,
+    pub beats: Vec<Beat>,
+    pub events: Vec<ClipEvent>,
+}
+
+impl BeatClip {
+    pub fn new(cue: &Cue, range: RangeInclusive<usize>) -> Self {
+        let start = *range.start();
+        let events = cueutils::events(cue)
+            .into_iter()
+            .filter(|e| range.contains(&(e.location as usize)))
+            .map(|mut event| {
+                event.location -= start as u16;
+                let mut relative_destination = false;
+                cueutils::remap_destination(&mut event, |dest| {
+                    if range.contains(&dest) {
+                        relative_destination = true;
+                        dest - start
+                    } else {
+                        dest
+                    }
+                });
+                ClipEvent {
+                    event,
+                    relative_destination,
+                }
+            })
+            .collect();
+        Self {
+            format: FORMAT.to_string(),
+            beats: cue.beats[range].to_vec(),
+            events,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(text: &str) -> Option<Self> {
+        serde_json::from_str::<Self>(text)
+            .ok()
+            .filter(|clip| clip.format == FORMAT && !clip.beats.is_empty())
+    }
+
+    /// Insert the beats and their events before beat `at` of a cue.
+    pub fn paste(&self, cue: &mut Cue, at: usize) {
+        let at = at.min(cue.beats.len());
+        let count = self.beats.len();
+        cueutils::insert_beats(cue, at, self.beats.clone());
+        for clip_event in &self.events {
+            let mut event = clip_event.event.clone();
+            event.location += at as u16;
+            cueutils::remap_destination(&mut event, |dest| {
+                if clip_event.relative_destination {
+                    dest + at
+                } else if dest >= at {
+                    // Outside the pasted beats, so it moves along with the rest of the cue
+                    dest + count
+                } else {
+                    dest
+                }
+            });
+            cue.events.push(event);
+        }
+    }
+
+    /// Put only the events onto existing beats, starting at beat `at`. Events that would land
+    /// past the end of the cue are left out.
+    pub fn paste_events(&self, cue: &mut Cue, at: usize) {
+        for clip_event in &self.events {
+            let mut event = clip_event.event.clone();
+            if at + event.location as usize >= cue.beats.len() {
+                continue;
+            }
+            event.location += at as u16;
+            if clip_event.relative_destination {
+                cueutils::remap_destination(&mut event, |dest| dest + at);
+            }
+            cue.events.push(event);
+        }
+        cue.recalculate_tempo_changes();
+    }
+}
+
+pub fn copy(app: &mut ClicksEditorApp) {
+    let clip = BeatClip::new(
+        &app.project_file.show.cues[app.selected_cue_idx],
+        app.selected_range(),
+    );
+    app.ctx.copy_text(clip.to_json());
+}
+
+/// Paste beats from the clipboard after the selection, or only their events onto the selection.
+pub fn paste(app: &mut ClicksEditorApp, text: &str, events_only: bool) {
+    let Some(clip) = BeatClip::from_json(text) else {
+        app.notifications.push(
+            Level::Warning,
+            "Nothing to paste",
+            "The clipboard does not hold any copied beats",
+        );
+        return;
+    };
+    let range = app.selected_range();
+    let cue = &mut app.project_file.show.cues[app.selected_cue_idx];
+    if events_only {
+        clip.paste_events(cue, *range.start());
+        return;
+    }
+
+    let at = if cue.beats.is_empty() {
+        0
+    } else {
+        range.end() + 1
+    };
+    clip.paste(cue, at);
+    cue.reorder_numbers();
+    cue.recalculate_tempo_changes();
+    app.select_beat(at, false);
+    app.select_beat(at + clip.beats.len() - 1, true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cueutils::testing::cue;
+    use common::event::{EventDescription, PauseEventBehaviour};
+
+    fn pause_jump(destination: usize) -> EventDescription {
+        EventDescription::PauseEvent {
+            behaviour: PauseEventBehaviour::Jump {
+                destination: destination as _,
+            },
+        }
+    }
+
+    /// Location and destination of every jumping pause in a cue.
+    fn pause_jumps(cue: &Cue) -> Vec<(usize, usize)> {
+        let mut jumps: Vec<(usize, usize)> = cueutils::events(cue)
+            .iter()
+            .filter_map(|e| match e.event {
+                Some(EventDescription::PauseEvent {
+                    behaviour: PauseEventBehaviour::Jump { destination },
+                }) => Some((e.location as usize, destination as usize)),
+                _ => None,
+            })
+            .collect();
+        jumps.sort();
+        jumps
+    }
+
+    #[test]
+    fn test_paste_destinations() {
+        let mut cue = cue(8);
+        // One jump within the copied beats and one to a beat before them
+        cue.events.push(Event::new(3, pause_jump(2)));
+        cue.events.push(Event::new(5, pause_jump(0)));
+        let clip = BeatClip::new(&cue, 2..=5);
+        assert_eq!(clip.events.len(), 2);
+
+        clip.paste(&mut cue, 6);
+        assert_eq!(cue.beats.len(), 12);
+        assert_eq!(pause_jumps(&cue), [(3, 2), (5, 0), (7, 6), (9, 0)]);
+    }
+
+    #[test]
+    fn test_paste_events_past_end() {
+        let mut source = cue(8);
+        source.events.push(Event::new(0, pause_jump(0)));
+        source.events.push(Event::new(3, pause_jump(1)));
+        let clip = BeatClip::new(&source, 0..=3);
+
+        // Only the first event still lands on a beat of the cue
+        let mut target = cue(8);
+        clip.paste_events(&mut target, 6);
+        assert_eq!(pause_jumps(&target), [(6, 6)]);
+    }
+}
@@ -1,5 +1,138 @@
-use common::cue::Cue;
+use common::{
+    beat::Beat,
+    cue::Cue,
+    event::{Event, EventDescription, PauseEventBehaviour},
+};
+use std::ops::{Range, RangeInclusive};
 
-pub fn apply_tempo_change(cue: &mut Cue, idx: usize) -> Result<(), ()> {
-    Err(())
+/// All events of a cue, in the same order as their indices in `cue.events`.
+pub fn events(cue: &Cue) -> Vec<Event> {
+    let mut events = cue.events.clone();
+    (0..events.len())
+        .filter_map(|i| events.get_mut(i as u8).cloned())
+        .collect()
+}
+
+/// The events on every beat of a cue, leaving out those past its last beat.
+pub fn events_by_beat(cue: &Cue) -> Vec<Vec<Event>> {
+    let mut events_at: Vec<Vec<Event>> = vec![vec![]; cue.beats.len()];
+    for event in events(cue) {
+        if let Some(events) = events_at.get_mut(event.location as usize) {
+            events.push(event);
+        }
+    }
+    events_at
+}
+
+/// Replace all events of a cue.
+pub fn set_events(cue: &mut Cue, events: Vec<Event>) {
+    while cue.events.len() > 0 {
+        cue.events.pop(0);
+    }
+    for event in events {
+        cue.events.push(event);
+    }
+}
+
+/// Run the beat an event jumps to, if it has one, through `map`.
+pub fn remap_destination(event: &mut Event, mut map: impl FnMut(usize) -> usize) {
+    match &mut event.event {
+        Some(EventDescription::JumpEvent { destination, .. }) => {
+            *destination = map(*destination as usize) as _
+        }
+        Some(EventDescription::PauseEvent {
+            behaviour: PauseEventBehaviour::Jump { destination },
+        }) => *destination = map(*destination as usize) as _,
+        _ => {}
+    }
+}
+
+/// Remove a range of beats together with the events on them.
+///
+/// Events and jump destinations after the range move back with their beats, and jumps into the
+/// range land on the beat that follows it.
+pub fn remove_beats(cue: &mut Cue, range: Range<usize>) {
+    let range = range.start.min(cue.beats.len())..range.end.min(cue.beats.len());
+    cue.beats.drain(range.clone());
+    let shift = |idx: usize| {
+        if idx >= range.end {
+            idx - range.len()
+        } else {
+            idx.min(range.start)
+        }
+    };
+    let events = events(cue)
+        .into_iter()
+        .filter(|e| !range.contains(&(e.location as usize)))
+        .map(|mut e| {
+            e.location = shift(e.location as usize) as u16;
+            remap_destination(&mut e, shift);
+            e
+        })
+        .collect();
+    set_events(cue, events);
+}
+
+/// Insert beats before `at`. Events and jump destinations from there on move along with their
+/// beats.
+pub fn insert_beats(cue: &mut Cue, at: usize, beats: Vec<Beat>) {
+    let at = at.min(cue.beats.len());
+    let count = beats.len();
+    cue.beats.splice(at..at, beats);
+    let shift = |idx: usize| if idx >= at { idx + count } else { idx };
+    let events = events(cue)
+        .into_iter()
+        .map(|mut e| {
+            e.location = shift(e.location as usize) as u16;
+            remap_destination(&mut e, shift);
+            e
+        })
+        .collect();
+    set_events(cue, events);
+}
+
+/// Widen a range of beats to the whole measures it touches.
+pub fn measure_range(cue: &Cue, range: RangeInclusive<usize>) -> Range<usize> {
+    let beats = &cue.beats;
+    let first_bar = beats[*range.start()].bar_number;
+    let last_bar = beats[*range.end()].bar_number;
+    let start = beats[..*range.start()]
+        .iter()
+        .rposition(|b| b.bar_number != first_bar)
+        .map_or(0, |i| i + 1);
+    let end = beats[*range.end()..]
+        .iter()
+        .position(|b| b.bar_number != last_bar)
+        .map_or(beats.len(), |i| i + range.end());
+    start..end
+}
+
+/// Cues to test with.
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+
+    /// A cue of `beats` beats in 4/4 at 120 BPM.
+    pub fn cue(beats: usize) -> Cue {
+        let mut cue = cue_in_bars(&vec![(4, 500000); beats.div_ceil(4)]);
+        cue.beats.truncate(beats);
+        cue
+    }
+
+    /// A cue of bars given as their number of beats and the length of those beats in
+    /// microseconds.
+    pub fn cue_in_bars(bars: &[(u8, u32)]) -> Cue {
+        let mut cue = Cue::empty();
+        cue.beats.clear();
+        for (i, &(count, length)) in bars.iter().enumerate() {
+            for beat in 0..count {
+                cue.beats.push(Beat {
+                    count: beat + 1,
+                    bar_number: (i + 1) as _,
+                    length,
+                });
+            }
+        }
+        cue
+    }
 }
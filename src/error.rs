@@ -0,0 +1,70 @@
+use std::{fmt::Display, path::PathBuf};
+
+/// Everything that can go wrong reading or writing a `ProjectFile`.
+#[derive(Debug)]
+pub enum ProjectError {
+    /// The show directory, the directory it should be created in, or a file in it doesn't exist
+    MissingDirectory(PathBuf),
+    PermissionDenied(PathBuf),
+    /// The show couldn't be encoded for writing to show.bin
+    Encode(PathBuf, postcard::Error),
+    /// show.bin couldn't be decoded
+    Decode(PathBuf, postcard::Error),
+    /// show.bin couldn't be decoded, and was written by another version of common than this
+    /// editor uses
+    VersionMismatch(PathBuf, String),
+    Json(PathBuf, serde_json::Error),
+    Io(PathBuf, std::io::Error),
+}
+
+impl ProjectError {
+    /// Sort an IO error on `path` into the errors the user can do something about.
+    pub fn io(path: PathBuf, err: std::io::Error) -> Self {
+        match err.kind() {
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied(path),
+            std::io::ErrorKind::NotFound => Self::MissingDirectory(path),
+            _ => Self::Io(path, err),
+        }
+    }
+}
+
+impl Display for ProjectError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingDirectory(path) => write!(f, "'{}' does not exist", path.display()),
+            Self::PermissionDenied(path) => {
+                write!(f, "Permission denied for '{}'", path.display())
+            }
+            Self::Encode(path, err) => {
+                write!(f, "Could not write the show to '{}': {err}", path.display())
+            }
+            Self::Decode(path, err) => {
+                write!(f, "'{}' is not a valid show file: {err}", path.display())
+            }
+            Self::VersionMismatch(path, version) => write!(
+                f,
+                "'{}' was written with common {version}, but this editor uses common {}",
+                path.display(),
+                common::VERSION
+            ),
+            Self::Json(path, err) => {
+                write!(f, "'{}' is not valid show JSON: {err}", path.display())
+            }
+            Self::Io(path, err) => write!(f, "'{}': {err}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ProjectError {}
+
+impl From<ProjectError> for std::io::Error {
+    fn from(err: ProjectError) -> Self {
+        let kind = match &err {
+            ProjectError::MissingDirectory(_) => std::io::ErrorKind::NotFound,
+            ProjectError::PermissionDenied(_) => std::io::ErrorKind::PermissionDenied,
+            ProjectError::Io(_, e) => e.kind(),
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, err)
+    }
+}
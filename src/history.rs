@@ -0,0 +1,136 @@
+use crate::app::ProjectFile;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Undo/redo history of the show, and of the time signatures the editor keeps beside it.
+///
+/// Snapshots are stored postcard encoded, the same way the show is written to `show.bin`. This
+/// keeps them compact, and lets any change to the show be detected by comparing bytes, no matter
+/// if it came from an action, the properties panel or anywhere else.
+///
+/// Every recorded state gets a revision number, which is how unsaved changes are told apart
+/// without encoding the show again: the show is dirty when its revision isn't the saved one.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    pub depth: usize,
+    #[serde(skip)]
+    undo_stack: VecDeque<(u64, Vec<u8>)>,
+    #[serde(skip)]
+    redo_stack: Vec<(u64, Vec<u8>)>,
+    #[serde(skip)]
+    baseline: (u64, Vec<u8>),
+    #[serde(skip)]
+    next_revision: u64,
+    #[serde(skip)]
+    saved_revision: u64,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            depth: Self::DEFAULT_DEPTH,
+            undo_stack: VecDeque::new(),
+            redo_stack: vec![],
+            baseline: (0, vec![]),
+            next_revision: 1,
+            saved_revision: 0,
+        }
+    }
+}
+
+impl History {
+    pub const DEFAULT_DEPTH: usize = 200;
+
+    fn encode(project_file: &ProjectFile) -> Vec<u8> {
+        project_file.snapshot().unwrap_or_default()
+    }
+
+    fn new_revision(&mut self) -> u64 {
+        self.next_revision += 1;
+        self.next_revision - 1
+    }
+
+    /// Forget all history and use the show as the new, saved starting point, e.g. after loading
+    /// a file.
+    pub fn reset(&mut self, project_file: &ProjectFile) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.baseline = (self.new_revision(), Self::encode(project_file));
+        self.saved_revision = self.baseline.0;
+    }
+
+    /// Record the show as the state that is saved to disk.
+    pub fn mark_saved(&mut self, project_file: &ProjectFile) {
+        self.commit(project_file);
+        self.saved_revision = self.baseline.0;
+    }
+
+    /// Revision of the last recorded state.
+    pub fn revision(&self) -> u64 {
+        self.baseline.0
+    }
+
+    /// Whether the last recorded state differs from the saved one.
+    pub fn is_dirty(&self) -> bool {
+        self.baseline.0 != self.saved_revision
+    }
+
+    /// Record the show as a new undo step if it differs from the last recorded state.
+    /// Returns true if a step was recorded.
+    pub fn commit(&mut self, project_file: &ProjectFile) -> bool {
+        let current = Self::encode(project_file);
+        if current == self.baseline.1 {
+            return false;
+        }
+        let current = (self.new_revision(), current);
+        self.undo_stack
+            .push_back(std::mem::replace(&mut self.baseline, current));
+        self.redo_stack.clear();
+        self.trim();
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo(&mut self, project_file: &mut ProjectFile) -> bool {
+        // Uncommitted edits are a step of their own, so they are the first thing to be undone.
+        self.commit(project_file);
+        let Some(prev) = self.undo_stack.pop_back() else {
+            return false;
+        };
+        if project_file.restore(&prev.1).is_err() {
+            return false;
+        }
+        self.redo_stack
+            .push(std::mem::replace(&mut self.baseline, prev));
+        true
+    }
+
+    pub fn redo(&mut self, project_file: &mut ProjectFile) -> bool {
+        self.commit(project_file);
+        let Some(next) = self.redo_stack.pop() else {
+            return false;
+        };
+        if project_file.restore(&next.1).is_err() {
+            return false;
+        }
+        self.undo_stack
+            .push_back(std::mem::replace(&mut self.baseline, next));
+        self.trim();
+        true
+    }
+
+    /// Drop the oldest steps until the history fits in `depth`.
+    pub fn trim(&mut self) {
+        while self.undo_stack.len() > self.depth {
+            self.undo_stack.pop_front();
+        }
+    }
+}
@@ -1,6 +1,6 @@
-use crate::app::ClicksEditorApp;
+use crate::{app::ClicksEditorApp, error::ProjectError, recovery};
 use rfd::MessageLevel;
-use std::path::PathBuf;
+use std::{ops::Range, path::PathBuf};
 
 pub fn pick_dir() -> Option<PathBuf> {
     if let Some(path) = rfd::FileDialog::new().pick_folder() {
@@ -29,15 +29,6 @@ pub fn pick_file() -> Option<PathBuf> {
     None
 }
 
-pub fn show_dialog(level: MessageLevel, title: String, content: String) {
-    rfd::MessageDialog::new()
-        .set_level(level)
-        .set_title(title)
-        .set_buttons(rfd::MessageButtons::Ok)
-        .set_description(content)
-        .show();
-}
-
 pub fn save(app: &mut ClicksEditorApp) {
     if app
         .project_file
@@ -48,38 +39,74 @@ pub fn save(app: &mut ClicksEditorApp) {
     {
         save_as(app);
     } else {
-        let _ = app.project_file.save();
+        let res = app.project_file.save();
+        saved(app, res);
     }
 }
 
 pub fn save_as(app: &mut ClicksEditorApp) {
     if let Some(dir) = pick_dir() {
-        let _ = app.project_file.save_as(dir);
+        let res = app.project_file.save_as(dir);
+        saved(app, res);
+    }
+}
+
+fn saved(app: &mut ClicksEditorApp, res: Result<(), ProjectError>) {
+    match res {
+        Ok(()) => app.history.mark_saved(&app.project_file),
+        Err(err) => app.notifications.error("Save failed", err),
     }
 }
 
 pub fn load(app: &mut ClicksEditorApp) {
     if let Some(dir) = pick_dir() {
-        let _ = app.project_file.load(dir);
+        if let Err(err) = app.project_file.load(dir) {
+            app.notifications.error("Load failed", err);
+            return;
+        }
+        app.history.reset(&app.project_file);
+        app.recovery_prompt = recovery::find_newer(&app.project_file);
+        crate::actions::action("show:refresh_audio_clips").run(app);
     }
 }
 
 pub fn export_json(app: &mut ClicksEditorApp) {
-    if let Some(dir) = save_file() && let Err(err) = app.project_file.export_json(dir.clone()) {
-        show_dialog(
-            MessageLevel::Error,
-            "Export failed".to_string(),
-            err.to_string(),
-        );
+    if let Some(dir) = save_file()
+        && let Err(err) = app.project_file.export_json(dir)
+    {
+        app.notifications.error("Export failed", err);
     }
 }
 
 pub fn import_json(app: &mut ClicksEditorApp) {
-    if let Some(dir) = pick_file() && let Err(err) = app.project_file.import_json(dir.clone()) {
-        show_dialog(
-            MessageLevel::Error,
-            "Import failed".to_string(),
-            err.to_string(),
-        );
+    if let Some(dir) = pick_file() {
+        match app.project_file.import_json(dir) {
+            Ok(()) => app.history.reset(&app.project_file),
+            Err(err) => app.notifications.error("Import failed", err),
+        }
+    }
+}
+
+pub fn export_midi(app: &mut ClicksEditorApp, cue_idxs: Range<usize>) {
+    let Some(path) = save_file() else {
+        return;
+    };
+    let include_click = rfd::MessageDialog::new()
+        .set_level(MessageLevel::Info)
+        .set_title("Export MIDI")
+        .set_description("Include a click track?")
+        .set_buttons(rfd::MessageButtons::YesNo)
+        .show()
+        == rfd::MessageDialogResult::Yes;
+    let cues: Vec<_> = cue_idxs
+        .map(|idx| {
+            (
+                app.project_file.show.cues[idx].clone(),
+                app.project_file.meters(idx),
+            )
+        })
+        .collect();
+    if let Err(err) = crate::midi::export(&cues, include_click, path) {
+        app.notifications.error("Export failed", err);
     }
 }
@@ -0,0 +1,213 @@
+use crate::{
+    cueutils,
+    simulate::Simulation,
+    timecode::{FrameRate, Timecode},
+};
+use common::{
+    cue::{Cue, Show},
+    event::EventDescription,
+};
+use std::path::PathBuf;
+
+/// Bits 64 to 79 of every frame, which a reader finds the start and direction of frames by
+const SYNC_WORD: [bool; 16] = [
+    false, false, true, true, true, true, true, true, true, true, true, true, true, true, false,
+    true,
+];
+
+/// Renders the SMPTE linear timecode the host puts out while playing a cue.
+#[derive(Debug, Clone)]
+pub struct LtcRenderer {
+    pub sample_rate: u32,
+    /// Silence between cues when rendering a whole show, in microseconds
+    pub cue_gap: u64,
+    pub volume: f32,
+}
+
+impl Default for LtcRenderer {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48000,
+            cue_gap: 2_000_000,
+            volume: 0.5,
+        }
+    }
+}
+
+impl LtcRenderer {
+    fn us_to_samples(&self, us: u64) -> usize {
+        (us * self.sample_rate as u64 / 1_000_000) as usize
+    }
+
+    /// The 80 bits of one frame, in the order they are sent.
+    pub fn frame_bits(tc: &Timecode) -> [bool; 80] {
+        let (h, m, s, f) = tc.hmsf();
+        let mut bits = [false; 80];
+        // Numbers are sent as binary coded decimal, least significant bit first
+        for (start, len, value) in [
+            (0, 4, f % 10),
+            (8, 2, f / 10),
+            (16, 4, s % 10),
+            (24, 3, s / 10),
+            (32, 4, m % 10),
+            (40, 3, m / 10),
+            (48, 4, h % 10),
+            (56, 2, h / 10),
+        ] {
+            for i in 0..len {
+                bits[start + i] = (value >> i) & 1 == 1;
+            }
+        }
+        bits[10] = tc.rate.drop_frame();
+        bits[64..].copy_from_slice(&SYNC_WORD);
+
+        // Even out the number of ones, so every frame starts on the same polarity
+        let parity_bit = if tc.rate == FrameRate::Fps25 { 59 } else { 27 };
+        bits[parity_bit] = bits.iter().filter(|b| **b).count() % 2 == 1;
+        bits
+    }
+
+    /// Render running timecode from `start` between two points in time, in biphase mark code:
+    /// the level flips at the start of every bit, and in the middle of bits that are 1.
+    fn render_region(
+        &self,
+        buf: &mut [f32],
+        (from, to): (u64, u64),
+        start: Timecode,
+        level: &mut bool,
+    ) {
+        let half_bits_per_sample = start.rate.fps() * 160.0 / self.sample_rate as f64;
+        let first = self.us_to_samples(from);
+        let last = self.us_to_samples(to).min(buf.len());
+        let mut current_half = None;
+        let mut current_frame = None;
+        let mut bits = [false; 80];
+        for (i, sample) in buf.iter_mut().enumerate().take(last).skip(first) {
+            let half = ((i - first) as f64 * half_bits_per_sample) as u64;
+            if current_half != Some(half) {
+                current_half = Some(half);
+                let bit_idx = half / 2;
+                let frame = bit_idx / 80;
+                if current_frame != Some(frame) {
+                    current_frame = Some(frame);
+                    bits = Self::frame_bits(&Timecode {
+                        frame: start.frame + frame as i64,
+                        rate: start.rate,
+                    });
+                }
+                if half % 2 == 0 || bits[(bit_idx % 80) as usize] {
+                    *level = !*level;
+                }
+            }
+            *sample = if *level { self.volume } else { -self.volume };
+        }
+    }
+
+    /// Render the timecode of a cue into `buf`, starting `offset` microseconds in. The cue is
+    /// played the way the host plays it, so timecode events are picked up again on every pass
+    /// through their beat.
+    pub fn render_cue_into(&self, cue: &Cue, buf: &mut [f32], offset: u64) {
+        let run = Simulation::default().run(cue);
+        let events_at = cueutils::events_by_beat(cue);
+
+        let mut level = false;
+        let mut running: Option<(Timecode, u64)> = None;
+        for step in &run.steps {
+            for event in &events_at[step.beat_idx] {
+                let started = match event.event {
+                    Some(EventDescription::TimecodeEvent { time }) => Timecode::from_instant(&time),
+                    Some(EventDescription::TimecodeStopEvent) => None,
+                    _ => continue,
+                };
+                if let Some((tc, from)) = running {
+                    self.render_region(buf, (offset + from, offset + step.time), tc, &mut level);
+                }
+                running = started.map(|tc| (tc, step.time));
+            }
+        }
+        if let Some((tc, from)) = running {
+            self.render_region(buf, (offset + from, offset + run.length), tc, &mut level);
+        }
+    }
+
+    pub fn cue_length(cue: &Cue) -> u64 {
+        Simulation::default().run(cue).length
+    }
+
+    pub fn render_cue(&self, cue: &Cue) -> Vec<f32> {
+        let mut buf = vec![0.0; self.us_to_samples(Self::cue_length(cue)) + 1];
+        self.render_cue_into(cue, &mut buf, 0);
+        buf
+    }
+
+    /// Render every cue in the show back to back, with `cue_gap` of silence in between.
+    pub fn render_show(&self, show: &Show) -> Vec<f32> {
+        let lengths: Vec<u64> = show.cues.iter().map(Self::cue_length).collect();
+        let total: u64 = lengths.iter().map(|l| l + self.cue_gap).sum();
+        let mut buf = vec![0.0; self.us_to_samples(total) + 1];
+        let mut offset = 0;
+        for (cue, length) in show.cues.iter().zip(lengths) {
+            self.render_cue_into(cue, &mut buf, offset);
+            offset += length + self.cue_gap;
+        }
+        buf
+    }
+
+    pub fn write_wav(&self, path: PathBuf, buf: &[f32]) -> Result<(), hound::Error> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: self.sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec)?;
+        for sample in buf {
+            writer.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        writer.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_bcd(bits: &[bool]) -> i64 {
+        bits.iter()
+            .enumerate()
+            .map(|(i, bit)| (*bit as i64) << i)
+            .sum()
+    }
+
+    #[test]
+    fn test_frame_bits() {
+        for rate in FrameRate::ALL {
+            let tc = Timecode::from_hmsf(12, 34, 56, 21, rate);
+            let bits = LtcRenderer::frame_bits(&tc);
+            assert_eq!(read_bcd(&bits[0..4]) + read_bcd(&bits[8..10]) * 10, 21);
+            assert_eq!(read_bcd(&bits[16..20]) + read_bcd(&bits[24..27]) * 10, 56);
+            assert_eq!(read_bcd(&bits[32..36]) + read_bcd(&bits[40..43]) * 10, 34);
+            assert_eq!(read_bcd(&bits[48..52]) + read_bcd(&bits[56..58]) * 10, 12);
+            assert_eq!(bits[10], rate.drop_frame());
+            assert_eq!(bits[64..], SYNC_WORD);
+            assert_eq!(bits.iter().filter(|b| **b).count() % 2, 0);
+        }
+    }
+
+    #[test]
+    fn test_frame_length() {
+        // A frame at 25 fps is 1920 samples at 48 kHz, and ends on the level it started on
+        let renderer = LtcRenderer::default();
+        let mut buf = vec![0.0; 1921];
+        let mut level = false;
+        renderer.render_region(
+            &mut buf,
+            (0, 40_000),
+            Timecode::from_hmsf(1, 0, 0, 0, FrameRate::Fps25),
+            &mut level,
+        );
+        assert!(!level);
+        assert!(buf[..1920].iter().all(|s| s.abs() == renderer.volume));
+        assert_eq!(buf[1920], 0.0);
+    }
+}
@@ -4,14 +4,36 @@
 mod actions;
 mod app;
 mod arrutil;
+mod cli;
+mod click;
 mod clip;
+mod clipboard;
 mod cueutils;
+mod error;
+mod history;
 mod io;
+mod ltc;
+mod meter;
+mod midi;
+mod notify;
 mod panel;
+mod preview;
+mod recovery;
+mod simulate;
+mod tempodetect;
+mod tempomap;
+mod timecode;
+mod validate;
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
 pub fn main() -> eframe::Result {
+    // A command means we're running headless, e.g. from a build script
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if cli::is_command(&args) {
+        std::process::exit(cli::run(&args));
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1920.0, 1080.0])
@@ -24,7 +46,7 @@ pub fn main() -> eframe::Result {
     };
 
     eframe::run_native(
-        "eframe template",
+        app::APP_ID,
         native_options,
         Box::new(|cc| Ok(app::ClicksEditorApp::new(cc))),
     )
@@ -0,0 +1,226 @@
+use crate::cueutils;
+use common::{
+    beat::Beat,
+    cue::Cue,
+    event::{Event, EventDescription},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    ops::{Range, RangeInclusive},
+};
+
+/// A time signature, and how its notes are grouped into clicks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Meter {
+    pub numerator: u8,
+    pub denominator: u8,
+    /// Click on every note, instead of on every group of notes in compound and odd meters
+    pub subdivide: bool,
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+            subdivide: false,
+        }
+    }
+}
+
+impl Display for Meter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl Meter {
+    pub fn new(numerator: u8, denominator: u8) -> Self {
+        Self {
+            numerator,
+            denominator,
+            subdivide: false,
+        }
+    }
+
+    /// 6/8, 9/8, 12/16 and the like, which are felt in dotted beats
+    pub fn is_compound(&self) -> bool {
+        self.denominator >= 8 && self.numerator > 3 && self.numerator % 3 == 0
+    }
+
+    /// Number of notes in each click of a bar. 6/8 gives [3, 3] and 7/8 gives [2, 2, 3], while
+    /// simple meters click every note.
+    pub fn groups(&self) -> Vec<u8> {
+        let n = self.numerator.max(1);
+        if self.subdivide || self.denominator < 8 || n <= 3 {
+            vec![1; n as usize]
+        } else if self.is_compound() {
+            vec![3; (n / 3) as usize]
+        } else if n % 2 == 1 {
+            let mut groups = vec![2; ((n - 3) / 2) as usize];
+            groups.push(3);
+            groups
+        } else {
+            vec![2; (n / 2) as usize]
+        }
+    }
+
+    /// Length of each beat of a bar in microseconds, given the length of a quarter note.
+    pub fn beat_lengths(&self, quarter_length: u32) -> Vec<u32> {
+        let note = quarter_length as u64 * 4 / self.denominator.max(1) as u64;
+        self.groups()
+            .iter()
+            .map(|group| (note * *group as u64) as u32)
+            .collect()
+    }
+}
+
+/// Time signatures of the bars of a cue, each from the bar number it is set at on, or None where
+/// it isn't known. The show only stores the length of beats, so the editor keeps these beside it.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Meters(Vec<(usize, Option<Meter>)>);
+
+impl Meters {
+    /// Time signature of a bar, if one has been set for it or a bar before it
+    pub fn at(&self, bar_number: usize) -> Option<Meter> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(bar, _)| *bar <= bar_number)
+            .and_then(|(_, meter)| *meter)
+    }
+
+    /// Set the time signature of a range of bars, keeping that of the bars after it.
+    pub fn set(&mut self, bars: RangeInclusive<usize>, meter: Meter) {
+        let after = *bars.end() + 1;
+        let meter_after = self.at(after);
+        self.0
+            .retain(|(bar, _)| !bars.contains(bar) && *bar != after);
+        self.0.push((*bars.start(), Some(meter)));
+        self.0.push((after, meter_after));
+        self.0.sort_by_key(|(bar, _)| *bar);
+    }
+
+    /// Set the time signature of a bar and every bar after it.
+    pub fn set_from(&mut self, bar_number: usize, meter: Meter) {
+        self.0.retain(|(bar, _)| *bar < bar_number);
+        self.0.push((bar_number, Some(meter)));
+    }
+
+    /// Every bar of the cue with the time signature it is in. Bars whose number of beats doesn't
+    /// fit their time signature have been edited since it was set, and are left out.
+    pub fn bars(&self, cue: &Cue) -> Vec<(Range<usize>, Meter)> {
+        let mut bars = vec![];
+        let mut start = 0;
+        while start < cue.beats.len() {
+            let bar_number = cue.beats[start].bar_number;
+            let len = cue.beats[start..]
+                .iter()
+                .take_while(|b| b.bar_number == bar_number)
+                .count();
+            if let Some(meter) = self.at(bar_number as usize)
+                && meter.groups().len() == len
+            {
+                bars.push((start..start + len, meter));
+            }
+            start += len;
+        }
+        bars
+    }
+}
+
+/// Rewrite the bars in `range` to `meter`, keeping the same number of bars.
+///
+/// Events stay in the bar they were in, on the same beat of it if the bar still has that many.
+/// Tempo changes inside the range are replaced with the click tempo of the new beats, and the
+/// tempo after the range is put back to what it was.
+pub fn apply(cue: &mut Cue, range: Range<usize>, meter: Meter, quarter_length: u32) {
+    let range = range.start.min(cue.beats.len())..range.end.min(cue.beats.len());
+    if range.is_empty() {
+        return;
+    }
+    let lengths = meter.beat_lengths(quarter_length);
+    let tempo_after = cue.beats.get(range.end).map(|b| b.length);
+
+    // Build the new bars, and where each old beat ends up
+    let mut new_beats = vec![];
+    let mut moved: HashMap<usize, usize> = HashMap::new();
+    let mut bar_start_new = range.start;
+    let mut idx = range.start;
+    while idx < range.end {
+        let bar_number = cue.beats[idx].bar_number;
+        let bar_len = cue.beats[idx..range.end]
+            .iter()
+            .take_while(|b| b.bar_number == bar_number)
+            .count();
+        for pos in 0..bar_len {
+            moved.insert(idx + pos, bar_start_new + pos.min(lengths.len() - 1));
+        }
+        for (i, length) in lengths.iter().enumerate() {
+            new_beats.push(Beat {
+                count: i as u8 + 1,
+                bar_number,
+                length: *length,
+            });
+        }
+        bar_start_new += lengths.len();
+        idx += bar_len;
+    }
+    let new_end = range.start + new_beats.len();
+    let shift = |idx: usize| {
+        if idx >= range.end {
+            idx + new_end - range.end
+        } else {
+            moved.get(&idx).copied().unwrap_or(idx)
+        }
+    };
+
+    let mut events: Vec<Event> = cueutils::events(cue)
+        .into_iter()
+        .filter(|e| {
+            !(range.contains(&(e.location as usize))
+                && matches!(e.event, Some(EventDescription::TempoChangeEvent { .. })))
+        })
+        .map(|mut e| {
+            e.location = shift(e.location as usize) as u16;
+            cueutils::remap_destination(&mut e, shift);
+            e
+        })
+        .collect();
+
+    // Click tempo wherever the beat length changes
+    let mut last_length = None;
+    for (i, beat) in new_beats.iter().enumerate() {
+        if last_length != Some(beat.length) {
+            events.push(Event::new(
+                (range.start + i) as u16,
+                EventDescription::TempoChangeEvent {
+                    tempo: (60_000_000.0 / beat.length.max(1) as f64).round() as _,
+                },
+            ));
+            last_length = Some(beat.length);
+        }
+    }
+    if let Some(length) = tempo_after
+        && last_length != Some(length)
+        && !events.iter().any(|e| {
+            e.location as usize == new_end
+                && matches!(e.event, Some(EventDescription::TempoChangeEvent { .. }))
+        })
+    {
+        events.push(Event::new(
+            new_end as u16,
+            EventDescription::TempoChangeEvent {
+                tempo: (60_000_000.0 / length.max(1) as f64).round() as _,
+            },
+        ));
+    }
+    events.sort_by_key(|e| e.location);
+
+    cue.beats.splice(range, new_beats);
+    cueutils::set_events(cue, events);
+    cue.reorder_numbers();
+    cue.recalculate_tempo_changes();
+}
@@ -0,0 +1,418 @@
+use crate::{
+    cueutils,
+    meter::{Meter, Meters},
+    tempomap::TempoMap,
+};
+use common::{
+    beat::Beat,
+    cue::{Cue, CueMetadata},
+    event::{Event, EventDescription},
+    mem::str::StaticString,
+};
+use midly::{
+    Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+    num::{u4, u7, u15, u24, u28},
+};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+enum Meta {
+    Tempo(u32),
+    TimeSignature(u8, u8),
+    Marker(String),
+    TrackName(String),
+}
+
+fn invalid(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+/// Build a cue from the tempo map of a Standard MIDI File.
+///
+/// Bars are split into beats the way the time signature dialog does it, so 6/8 gives two dotted
+/// quarter beats per bar, and the time signatures are returned along with the cue. Tempo
+/// changes become `TempoChangeEvent`s on the beat they land on, and markers become rehearsal
+/// marks. Notes are ignored, only their position is used to find the end of the song.
+pub fn import_cue(path: PathBuf) -> Result<(Cue, Meters), std::io::Error> {
+    let data = std::fs::read(&path)?;
+    let smf = Smf::parse(&data).map_err(|e| invalid(e.to_string()))?;
+    let tpq = match smf.header.timing {
+        Timing::Metrical(tpq) => tpq.as_int() as u64,
+        Timing::Timecode(_, _) => {
+            return Err(invalid(
+                "MIDI files timed in SMPTE frames have no tempo map to import".to_string(),
+            ));
+        }
+    };
+
+    let mut metas: Vec<(u64, Meta)> = vec![];
+    let mut end_tick: u64 = 0;
+    for track in &smf.tracks {
+        let mut tick: u64 = 0;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            let meta = match event.kind {
+                TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => Meta::Tempo(tempo.as_int()),
+                TrackEventKind::Meta(MetaMessage::TimeSignature(num, den_pow, _, _)) => {
+                    Meta::TimeSignature(num, 1 << den_pow)
+                }
+                TrackEventKind::Meta(MetaMessage::Marker(text)) => {
+                    Meta::Marker(String::from_utf8_lossy(text).trim().to_string())
+                }
+                TrackEventKind::Meta(MetaMessage::TrackName(text)) => {
+                    Meta::TrackName(String::from_utf8_lossy(text).trim().to_string())
+                }
+                _ => {
+                    end_tick = end_tick.max(tick);
+                    continue;
+                }
+            };
+            metas.push((tick, meta));
+        }
+    }
+    // Sorting is stable, so events at the same tick keep their order within the file
+    metas.sort_by_key(|(tick, _)| *tick);
+    end_tick = end_tick.max(metas.last().map(|(tick, _)| *tick).unwrap_or(0));
+
+    let name = metas
+        .iter()
+        .find_map(|(_, meta)| match meta {
+            Meta::TrackName(name) if !name.is_empty() => Some(name.clone()),
+            _ => None,
+        })
+        .or(path.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .unwrap_or_default();
+
+    let mut cue = Cue::empty();
+    cue.beats.clear();
+    let mut meters = Meters::default();
+    cue.metadata = CueMetadata {
+        name: StaticString::new(&name),
+        human_ident: StaticString::new("000"),
+    };
+
+    // Microseconds per quarter note at a tick, taking every tempo change up to it into account
+    let tempo_at = |tick: u64| {
+        metas
+            .iter()
+            .take_while(|(t, _)| *t <= tick)
+            .filter_map(|(_, meta)| match meta {
+                Meta::Tempo(tempo) => Some(*tempo),
+                _ => None,
+            })
+            .last()
+            .unwrap_or(500000)
+    };
+
+    let mut tick: u64 = 0;
+    let mut numerator: u8 = 4;
+    let mut denominator: u8 = 4;
+    let mut bar_number = 1;
+    let mut last_meter = None;
+    // Beat length and time signature of the last tempo change. Beats can change length without
+    // a new tempo, at a new note value or between the uneven groups of 7/8
+    let mut last_change: Option<(u64, u8, u8)> = None;
+    let mut meta_idx = 0;
+    loop {
+        // Time signatures take effect at the start of the bar they're in
+        while let Some((meta_tick, meta)) = metas.get(meta_idx)
+            && *meta_tick <= tick
+        {
+            if let Meta::TimeSignature(num, den) = meta {
+                numerator = (*num).max(1);
+                denominator = (*den).max(1);
+            }
+            meta_idx += 1;
+        }
+        if tick >= end_tick && !cue.beats.is_empty() {
+            break;
+        }
+
+        let meter = Meter::new(numerator, denominator);
+        if last_meter != Some(meter) {
+            meters.set_from(bar_number as usize, meter);
+            last_meter = Some(meter);
+        }
+        let note_ticks = (tpq * 4 / denominator as u64).max(1);
+        for (i, group) in meter.groups().into_iter().enumerate() {
+            let count = i as u8 + 1;
+            let beat_start = tick;
+            let beat_end = tick + note_ticks * group as u64;
+
+            // A tempo change can land in the middle of a beat, so add the beat up in pieces
+            let mut length_us: u64 = 0;
+            let mut piece_start = beat_start;
+            for (change_tick, _) in metas.iter().filter(|(t, meta)| {
+                *t > beat_start && *t < beat_end && matches!(meta, Meta::Tempo(_))
+            }) {
+                length_us += (change_tick - piece_start) * tempo_at(piece_start) as u64 / tpq;
+                piece_start = *change_tick;
+            }
+            length_us += (beat_end - piece_start) * tempo_at(piece_start) as u64 / tpq;
+
+            let beat_idx = cue.beats.len() as u16;
+            cue.beats.push(Beat {
+                count,
+                bar_number,
+                length: length_us as u32,
+            });
+
+            let change = (length_us, numerator, denominator);
+            if last_change != Some(change) {
+                let bpm = (60_000_000.0 / length_us.max(1) as f64).round();
+                cue.events.push(Event::new(
+                    beat_idx,
+                    EventDescription::TempoChangeEvent { tempo: bpm as _ },
+                ));
+                last_change = Some(change);
+            }
+
+            for (_, meta) in metas
+                .iter()
+                .filter(|(t, _)| *t >= beat_start && *t < beat_end)
+            {
+                if let Meta::Marker(label) = meta
+                    && !label.is_empty()
+                {
+                    cue.events.push(Event::new(
+                        beat_idx,
+                        EventDescription::RehearsalMarkEvent {
+                            label: StaticString::new(label),
+                        },
+                    ));
+                }
+            }
+
+            tick = beat_end;
+        }
+        bar_number += 1;
+    }
+
+    Ok((cue, meters))
+}
+
+/// Ticks per quarter note in exported files. Beats are written as the note value of the time
+/// signature of their bar, or as quarter notes where that isn't known.
+const EXPORT_TPQ: u64 = 480;
+const CLICK_CHANNEL: u8 = 9;
+const CLICK_KEY_DOWNBEAT: u8 = 76;
+const CLICK_KEY_BEAT: u8 = 77;
+
+enum ExportMeta {
+    Tempo(u32),
+    TimeSignature(u8, u8),
+    Marker(String),
+}
+
+/// Write the timing of `cues`, played back to back, to a Standard MIDI File, each with the time
+/// signatures of its bars.
+///
+/// The first track holds the tempo map, time signatures and markers for cue names and rehearsal
+/// marks. If `include_click` is set, a second track has a woodblock note on every beat, with the
+/// downbeats on a higher note.
+pub fn export(
+    cues: &[(Cue, Meters)],
+    include_click: bool,
+    path: PathBuf,
+) -> Result<(), std::io::Error> {
+    let mut metas: Vec<(u64, ExportMeta)> = vec![];
+    let mut clicks: Vec<(u64, u8)> = vec![];
+    let mut tick: u64 = 0;
+    let mut last_tempo = None;
+    let mut last_meter = None;
+    for (cue, meters) in cues {
+        let cue_name = format!(
+            "{} {}",
+            cue.metadata.human_ident.str(),
+            cue.metadata.name.str()
+        );
+        metas.push((tick, ExportMeta::Marker(cue_name.trim().to_string())));
+        let events = cueutils::events(cue);
+        let bars = meters.bars(cue);
+        let mut map = TempoMap::new(cue);
+        map.set_meters(cue, meters);
+
+        for (beat_idx, beat) in cue.beats.iter().enumerate() {
+            if beat.count == 1 || beat_idx == 0 {
+                let meter = match bars.iter().find(|(range, _)| range.start == beat_idx) {
+                    Some((_, meter)) => (meter.numerator, meter.denominator),
+                    None => {
+                        let bar_length =
+                            cue.beats
+                                .iter()
+                                .filter(|b| b.bar_number == beat.bar_number)
+                                .count()
+                                .clamp(1, u8::MAX as usize) as u8;
+                        (bar_length, 4)
+                    }
+                };
+                if last_meter != Some(meter) {
+                    metas.push((tick, ExportMeta::TimeSignature(meter.0, meter.1)));
+                    last_meter = Some(meter);
+                }
+            }
+            // MIDI tempo is the length of a quarter note
+            let notes = map.beat_notes[beat_idx];
+            let tempo = (beat.length as f64 / notes).round() as u32;
+            if last_tempo != Some(tempo) {
+                metas.push((tick, ExportMeta::Tempo(tempo)));
+                last_tempo = Some(tempo);
+            }
+            for event in events.iter().filter(|e| e.location as usize == beat_idx) {
+                if let Some(EventDescription::RehearsalMarkEvent { label }) = &event.event {
+                    metas.push((tick, ExportMeta::Marker(label.str().to_string())));
+                }
+            }
+            clicks.push((
+                tick,
+                if beat.count == 1 {
+                    CLICK_KEY_DOWNBEAT
+                } else {
+                    CLICK_KEY_BEAT
+                },
+            ));
+            tick += (EXPORT_TPQ as f64 * notes).round() as u64;
+        }
+    }
+    let end_tick = tick;
+
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(u15::new(EXPORT_TPQ as u16)),
+    ));
+
+    let mut conductor = vec![];
+    let mut last_tick = 0;
+    for (tick, meta) in &metas {
+        let message = match meta {
+            ExportMeta::Tempo(length) => MetaMessage::Tempo(u24::new((*length).min(0xFFFFFF))),
+            ExportMeta::TimeSignature(num, den) => {
+                MetaMessage::TimeSignature(*num, (*den).max(1).trailing_zeros() as u8, 24, 8)
+            }
+            ExportMeta::Marker(text) => MetaMessage::Marker(text.as_bytes()),
+        };
+        conductor.push(TrackEvent {
+            delta: u28::new((tick - last_tick) as u32),
+            kind: TrackEventKind::Meta(message),
+        });
+        last_tick = *tick;
+    }
+    conductor.push(TrackEvent {
+        delta: u28::new((end_tick - last_tick) as u32),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+    smf.tracks.push(conductor);
+
+    if include_click {
+        let mut click_track = vec![TrackEvent {
+            delta: u28::new(0),
+            kind: TrackEventKind::Meta(MetaMessage::TrackName(b"Click")),
+        }];
+        let note_length = EXPORT_TPQ / 8;
+        let mut last_tick = 0;
+        for (tick, key) in &clicks {
+            for (at, vel) in [(*tick, 100), (*tick + note_length, 0)] {
+                click_track.push(TrackEvent {
+                    delta: u28::new((at - last_tick) as u32),
+                    kind: TrackEventKind::Midi {
+                        channel: u4::new(CLICK_CHANNEL),
+                        message: if vel > 0 {
+                            MidiMessage::NoteOn {
+                                key: u7::new(*key),
+                                vel: u7::new(vel),
+                            }
+                        } else {
+                            MidiMessage::NoteOff {
+                                key: u7::new(*key),
+                                vel: u7::new(0),
+                            }
+                        },
+                    },
+                });
+                last_tick = at;
+            }
+        }
+        click_track.push(TrackEvent {
+            delta: u28::new(end_tick.saturating_sub(last_tick) as u32),
+            kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+        });
+        smf.tracks.push(click_track);
+    }
+
+    smf.save(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tempo_changes(cue: &Cue) -> Vec<(usize, u32)> {
+        cueutils::events(cue)
+            .iter()
+            .filter_map(|e| match e.event {
+                Some(EventDescription::TempoChangeEvent { tempo }) => {
+                    Some((e.location as usize, tempo as u32))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_import_meter_change() {
+        // Two bars of 4/4 and one of 6/8, all at 120 BPM
+        let events = [
+            (0, MetaMessage::TimeSignature(4, 2, 24, 8)),
+            (0, MetaMessage::Tempo(u24::new(500000))),
+            (3840, MetaMessage::TimeSignature(6, 3, 24, 8)),
+            (1440, MetaMessage::EndOfTrack),
+        ];
+        let mut smf = Smf::new(Header::new(
+            Format::SingleTrack,
+            Timing::Metrical(u15::new(480)),
+        ));
+        smf.tracks.push(
+            events
+                .into_iter()
+                .map(|(delta, message)| TrackEvent {
+                    delta: u28::new(delta),
+                    kind: TrackEventKind::Meta(message),
+                })
+                .collect(),
+        );
+        let path = std::env::temp_dir().join("clicks-editor-test-meter-change.mid");
+        smf.save(&path).unwrap();
+
+        let (cue, meters) = import_cue(path).unwrap();
+        assert_eq!(cue.beats.len(), 10);
+        assert_eq!(cue.beats[7].length, 500000);
+        assert_eq!(cue.beats[8].length, 750000);
+        // The dotted quarters of the 6/8 bar click slower than the quarters before them
+        assert_eq!(tempo_changes(&cue), [(0, 120), (8, 80)]);
+        assert_eq!(meters.at(3), Some(Meter::new(6, 8)));
+    }
+
+    #[test]
+    fn test_export_round_trip() {
+        // A bar of 4/4 followed by two of 6/8 and one of 12/8
+        let cue =
+            cueutils::testing::cue_in_bars(&[(4, 500000), (2, 750000), (2, 750000), (4, 600000)]);
+        let mut meters = Meters::default();
+        meters.set_from(1, Meter::new(4, 4));
+        meters.set(2..=3, Meter::new(6, 8));
+        meters.set_from(4, Meter::new(12, 8));
+
+        let path = std::env::temp_dir().join("clicks-editor-test-round-trip.mid");
+        export(&[(cue.clone(), meters.clone())], false, path.clone()).unwrap();
+        let (imported, imported_meters) = import_cue(path).unwrap();
+
+        let beats = |cue: &Cue| {
+            cue.beats
+                .iter()
+                .map(|b| (b.count, b.bar_number as usize, b.length))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(beats(&imported), beats(&cue));
+        assert_eq!(imported_meters.bars(&imported), meters.bars(&cue));
+    }
+}
@@ -0,0 +1,232 @@
+use crate::app;
+use std::{
+    fmt::Display,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+const LOG_FILE: &str = "clicks-editor.log";
+const TOAST_WIDTH: f32 = 320.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Level {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Info => "INFO",
+            Self::Warning => "WARNING",
+            Self::Error => "ERROR",
+        }
+    }
+
+    fn color(&self, ui: &egui::Ui) -> egui::Color32 {
+        match self {
+            Self::Info => ui.visuals().text_color(),
+            Self::Warning => ui.visuals().warn_fg_color,
+            Self::Error => ui.visuals().error_fg_color,
+        }
+    }
+
+    /// How long a toast of this level stays up
+    fn duration(&self) -> Duration {
+        match self {
+            Self::Info => Duration::from_secs(4),
+            Self::Warning => Duration::from_secs(8),
+            Self::Error => Duration::from_secs(12),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub level: Level,
+    pub time: String,
+    pub title: String,
+    pub message: String,
+}
+
+/// Non-blocking notifications shown as toasts in the corner of the window, and kept in a log
+/// that can be opened from the View menu. Everything is also appended to a log file in the
+/// editor's storage directory, so it survives a restart.
+#[derive(Debug, Default)]
+pub struct Notifications {
+    toasts: Vec<(Instant, Entry)>,
+    pub log: Vec<Entry>,
+    pub show_log: bool,
+}
+
+impl Notifications {
+    pub fn push(&mut self, level: Level, title: impl Into<String>, message: impl Into<String>) {
+        let entry = Entry {
+            level,
+            time: timestamp(SystemTime::now()),
+            title: title.into(),
+            message: message.into(),
+        };
+        append_to_file(&entry);
+        self.toasts.push((Instant::now(), entry.clone()));
+        self.log.push(entry);
+    }
+
+    pub fn info(&mut self, title: impl Into<String>, message: impl Into<String>) {
+        self.push(Level::Info, title, message);
+    }
+
+    pub fn error(&mut self, title: impl Into<String>, err: impl Display) {
+        self.push(Level::Error, title, err.to_string());
+    }
+
+    pub fn display(&mut self, ctx: &egui::Context) {
+        let now = Instant::now();
+        self.toasts
+            .retain(|(shown, entry)| now.duration_since(*shown) < entry.level.duration());
+
+        let mut dismissed = None;
+        let mut open_log = false;
+        egui::Area::new(egui::Id::new("notification_toasts"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-10.0, -10.0))
+            .order(egui::Order::Foreground)
+            .interactable(true)
+            .show(ctx, |ui| {
+                ui.set_max_width(TOAST_WIDTH);
+                for (idx, (_, entry)) in self.toasts.iter().enumerate() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.set_width(TOAST_WIDTH);
+                        ui.horizontal(|ui| {
+                            ui.label(
+                                egui::RichText::new(&entry.title)
+                                    .strong()
+                                    .color(entry.level.color(ui)),
+                            );
+                            ui.with_layout(
+                                egui::Layout::right_to_left(egui::Align::Center),
+                                |ui| {
+                                    if ui
+                                        .small_button(egui_material_icons::icons::ICON_CLOSE)
+                                        .clicked()
+                                    {
+                                        dismissed = Some(idx);
+                                    }
+                                    if ui.small_button("Log").clicked() {
+                                        open_log = true;
+                                    }
+                                },
+                            );
+                        });
+                        ui.label(&entry.message);
+                    });
+                }
+            });
+        if let Some(idx) = dismissed {
+            self.toasts.remove(idx);
+        }
+        if open_log {
+            self.show_log = true;
+        }
+        if let Some(next) = self
+            .toasts
+            .iter()
+            .map(|(shown, entry)| (*shown + entry.level.duration()).saturating_duration_since(now))
+            .min()
+        {
+            ctx.request_repaint_after(next);
+        }
+
+        let mut show_log = self.show_log;
+        egui::Window::new("Log")
+            .open(&mut show_log)
+            .default_size([500.0, 300.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Open log file").clicked()
+                        && let Some(path) = log_path()
+                        && let Err(err) = open::that(&path)
+                    {
+                        self.error("Could not open log file", err);
+                    }
+                    if ui.button("Clear").clicked() {
+                        self.log.clear();
+                    }
+                });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink(false)
+                    .show(ui, |ui| {
+                        egui::Grid::new("log_grid").striped(true).show(ui, |ui| {
+                            for entry in &self.log {
+                                ui.monospace(&entry.time);
+                                ui.label(
+                                    egui::RichText::new(entry.level.label())
+                                        .color(entry.level.color(ui)),
+                                );
+                                ui.label(format!("{}: {}", entry.title, entry.message));
+                                ui.end_row();
+                            }
+                        });
+                    });
+            });
+        self.show_log = show_log;
+    }
+}
+
+pub fn log_path() -> Option<PathBuf> {
+    eframe::storage_dir(app::APP_ID).map(|dir| dir.join(LOG_FILE))
+}
+
+/// The log file is only a convenience, so failing to write it is not reported anywhere.
+fn append_to_file(entry: &Entry) {
+    let Some(path) = log_path() else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Ok(mut file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+    {
+        let _ = writeln!(
+            file,
+            "{} [{}] {}: {}",
+            entry.time,
+            entry.level.label(),
+            entry.title,
+            entry.message
+        );
+    }
+}
+
+/// Format a time as "YYYY-MM-DD HH:MM:SS" in UTC.
+fn timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
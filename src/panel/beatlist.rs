@@ -1,4 +1,4 @@
-use crate::app::ClicksEditorApp;
+use crate::{app::ClicksEditorApp, timecode};
 use egui::vec2;
 
 pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
@@ -11,22 +11,33 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
     egui::ScrollArea::vertical().show(ui, |ui| {
         egui::Grid::new("beatlist")
             .striped(true)
-            .num_columns(5)
+            .num_columns(7)
             .spacing(vec2(0.0, height))
             .show(ui, |ui| {
                 ui.label("Cue");
                 ui.label("#");
                 ui.label("Bar");
                 ui.label("Beat");
+                ui.label("Time");
+                ui.label("Timecode");
                 ui.label("");
                 ui.end_row();
 
                 for (cue_idx, cue) in app.project_file.show.cues.iter().enumerate() {
+                    let beat_times = app.beat_times.get(app.history.revision(), cue_idx, cue);
                     for (beat_idx, beat) in cue.get_beats().iter().enumerate() {
                         ui.label(cue_idx.to_string());
                         ui.label(beat_idx.to_string());
                         ui.label(beat.bar_number.to_string());
                         ui.label(beat.count.to_string());
+                        match beat_times.times.get(beat_idx).copied().flatten() {
+                            Some(time) => ui.monospace(timecode::format_time(time)),
+                            None => ui.label(""),
+                        };
+                        match beat_times.timecodes.get(beat_idx).copied().flatten() {
+                            Some(tc) => ui.monospace(tc.to_string()),
+                            None => ui.label(""),
+                        };
                         //ui.label(
                         //    beat.events
                         //        .iter()
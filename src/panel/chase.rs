@@ -0,0 +1,133 @@
+use crate::{
+    app::ClicksEditorApp,
+    timecode::{self, ChasePlan, ChasePoints, ChasePolicy, FrameRate, Timecode},
+};
+
+/// Settings of the timecode planning dialog while it is open.
+#[derive(Debug, Clone, Copy)]
+pub struct ChasePrompt {
+    /// Start timecode as hours, minutes, seconds and frames
+    pub start: [i64; 4],
+    pub rate: FrameRate,
+    pub points: ChasePoints,
+    pub policy: ChasePolicy,
+}
+
+impl ChasePrompt {
+    pub fn new(rate: FrameRate) -> Self {
+        Self {
+            start: [1, 0, 0, 0],
+            rate,
+            points: ChasePoints::default(),
+            policy: ChasePolicy::default(),
+        }
+    }
+
+    fn plan(&self) -> ChasePlan {
+        let [h, m, s, f] = self.start;
+        ChasePlan {
+            start: Timecode::from_hmsf(h, m, s, f, self.rate),
+            points: self.points,
+            policy: self.policy,
+        }
+    }
+}
+
+/// Ask where timecode should run in the show, then replace its timecode events.
+pub fn display(app: &mut ClicksEditorApp, ctx: &egui::Context) {
+    let Some(mut prompt) = app.chase_prompt else {
+        return;
+    };
+
+    let mut apply = false;
+    let mut close = false;
+    egui::Modal::new(egui::Id::new("chase_prompt")).show(ctx, |ui| {
+        ui.heading("Plan timecode");
+        ui.label("Replaces the timecode events of every cue.");
+        ui.separator();
+        egui::Grid::new("chase_prompt_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Frame rate:");
+                egui::ComboBox::from_id_salt("chase_rate")
+                    .selected_text(prompt.rate.to_string())
+                    .show_ui(ui, |ui| {
+                        for rate in FrameRate::ALL {
+                            ui.selectable_value(&mut prompt.rate, rate, rate.to_string());
+                        }
+                    });
+                ui.end_row();
+                ui.label("Start at:");
+                ui.horizontal(|ui| {
+                    let max_frame = prompt.rate.nominal() as i64 - 1;
+                    for (val, max) in prompt.start.iter_mut().zip([23, 59, 59, max_frame]) {
+                        ui.add(
+                            egui::DragValue::new(val)
+                                .speed(0.1)
+                                .custom_formatter(|n, _| format!("{n:02}"))
+                                .range(0..=max),
+                        );
+                    }
+                });
+                ui.end_row();
+                ui.label("Timecode events at:");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut prompt.points, ChasePoints::CueStarts, "Cue starts");
+                    ui.radio_value(
+                        &mut prompt.points,
+                        ChasePoints::RehearsalMarks,
+                        "Rehearsal marks",
+                    )
+                    .on_hover_text("Cues without rehearsal marks get timecode from their start");
+                });
+                ui.end_row();
+                ui.label("Each cue:");
+                ui.vertical(|ui| {
+                    ui.radio_value(
+                        &mut prompt.policy,
+                        ChasePolicy::Continuous,
+                        "Carries on from the one before",
+                    )
+                    .on_hover_text("Pauses and waits between cues are not counted");
+                    ui.radio_value(
+                        &mut prompt.policy,
+                        ChasePolicy::RestartEachCue,
+                        "Starts at the start time",
+                    );
+                    ui.radio_value(
+                        &mut prompt.policy,
+                        ChasePolicy::HourPerCue,
+                        "Starts an hour after the one before",
+                    );
+                });
+                ui.end_row();
+            });
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Apply").clicked() {
+                apply = true;
+            }
+            if ui.button("Cancel").clicked() {
+                close = true;
+            }
+        });
+    });
+
+    if apply {
+        let added = timecode::plan_chase(&mut app.project_file.show, &prompt.plan());
+        app.frame_rate = prompt.rate;
+        app.notifications.info(
+            "Timecode planned",
+            format!(
+                "{added} timecode event{} at {}",
+                if added == 1 { "" } else { "s" },
+                prompt.rate
+            ),
+        );
+        app.chase_prompt = None;
+    } else if close {
+        app.chase_prompt = None;
+    } else {
+        app.chase_prompt = Some(prompt);
+    }
+}
@@ -1,5 +1,29 @@
-use crate::app::ClicksEditorApp;
+use crate::{app::ClicksEditorApp, clip::ClipManager, meter::Meters, tempodetect};
 use egui::vec2;
+use std::path::PathBuf;
+
+/// A WAV file waiting to be put into a clip slot.
+#[derive(Debug, Clone)]
+pub struct ClipImport {
+    pub source: PathBuf,
+    /// Format of the file, read once when it is picked
+    pub spec: Result<hound::WavSpec, String>,
+    pub channel_idx: usize,
+    pub clip_idx: usize,
+}
+
+impl ClipImport {
+    pub fn new(source: PathBuf, channel_idx: usize, clip_idx: usize) -> Self {
+        Self {
+            spec: hound::WavReader::open(&source)
+                .map(|r| r.spec())
+                .map_err(|e| e.to_string()),
+            source,
+            channel_idx,
+            clip_idx,
+        }
+    }
+}
 
 pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
     egui::TopBottomPanel::bottom("clip_buttons")
@@ -10,32 +34,107 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
     let height = 7.0;
     egui::Grid::new("cliplist")
         .striped(true)
-        .num_columns(4)
+        .num_columns(7)
         .spacing(vec2(20.0, height))
         .show(ui, |ui| {
             ui.label("Ch");
             ui.label("Idx");
+            ui.label("Name");
+            ui.label("Notes");
             ui.label("Length");
+            ui.label("Tempo");
             ui.end_row();
 
-            for (i, val) in app.clip_manager.clips.iter().enumerate() {
-                let ch_idx = val.0 .0;
-                let cl_idx = val.0 .1;
-                let clip = val.1;
+            let mut make_cue = None;
+            let mut edited = false;
+
+            let mut keys: Vec<(usize, usize)> = app.clip_manager.clips.keys().copied().collect();
+            keys.sort();
+            for (ch_idx, cl_idx) in keys {
+                let Some(clip) = app.clip_manager.clips.get_mut(&(ch_idx, cl_idx)) else {
+                    continue;
+                };
 
                 ui.label(ch_idx.to_string());
                 ui.label(cl_idx.to_string());
-                ui.label(format!(
-                    "{} min {} sec ({} samples)",
-                    clip.length / 48000 / 60,
-                    clip.length / 48000 % 60,
-                    clip.length,
-                ));
-                if ui.small_button("Open").clicked() {
-                    let _ = open::that(clip.path.clone());
-                }
+                let file_name = clip.label();
+                let name = ui
+                    .add(
+                        egui::TextEdit::singleline(&mut clip.name)
+                            .hint_text(file_name)
+                            .desired_width(120.0),
+                    )
+                    .on_hover_text(if clip.source.is_empty() {
+                        "Copied into the show by hand".to_string()
+                    } else {
+                        format!("Imported from {}", clip.source)
+                    });
+                let notes = ui.add(
+                    egui::TextEdit::singleline(&mut clip.notes).desired_width(160.0),
+                );
+                edited |= name.lost_focus() || notes.lost_focus();
+                let seconds = clip.duration().round() as usize;
+                ui.label(format!("{} min {} sec", seconds / 60, seconds % 60))
+                    .on_hover_text(format!(
+                        "{} frames at {} Hz, {} channel{}",
+                        clip.length,
+                        clip.sample_rate,
+                        clip.channels,
+                        if clip.channels == 1 { "" } else { "s" }
+                    ));
+                match clip.tempo {
+                    Some(detection) => ui
+                        .label(format!("~{:.1} BPM", detection.bpm))
+                        .on_hover_text(format!(
+                            "Detected from the clip, {:.0}% sure",
+                            detection.confidence * 100.0
+                        )),
+                    None => ui.label("-"),
+                };
+                ui.horizontal(|ui| {
+                    if ui.small_button("Open").clicked() {
+                        let _ = open::that(clip.path.clone());
+                    }
+                    if ui
+                        .add_enabled(clip.tempo.is_some(), egui::Button::new("Make cue").small())
+                        .on_hover_text("Add a cue at the tempo of the clip, which plays the clip")
+                        .clicked()
+                    {
+                        make_cue = Some((ch_idx, cl_idx));
+                    }
+                });
                 ui.end_row();
             }
+
+            if edited
+                && let Err(err) = app.clip_manager.save_manifest(&app.project_file.path)
+            {
+                app.notifications.error("Could not save clip names", err);
+            }
+
+            if let Some((ch_idx, cl_idx)) = make_cue
+                && let Some(clip) = app.clip_manager.clips.get(&(ch_idx, cl_idx))
+                && let Some(detection) = clip.tempo
+            {
+                let name = clip.label();
+                let cue = tempodetect::cue_skeleton(
+                    &detection,
+                    &name,
+                    clip.length,
+                    clip.sample_rate,
+                    ch_idx,
+                    cl_idx,
+                    4,
+                );
+                let idx = (app.selected_cue_idx + 1).min(app.project_file.show.cues.len());
+                app.project_file.insert_cue(idx, cue, Meters::default());
+                app.selected_cue_idx = idx;
+                app.select_beat(0, false);
+                app.notifications.info(
+                    "Cue created",
+                    format!("{name} at {:.0} BPM", detection.bpm),
+                );
+            }
         });
 }
 
@@ -44,5 +143,145 @@ pub fn buttons(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
         if ui.button("Refresh").clicked() {
             let _ = app.clip_manager.import(app.project_file.path.clone());
         }
+        let (done, total) = app.clip_manager.progress;
+        if done < total {
+            ui.add(
+                egui::ProgressBar::new(done as f32 / total as f32)
+                    .text(format!("Reading clips {done}/{total}"))
+                    .desired_width(160.0),
+            );
+        }
+        if ui
+            .add_enabled(
+                !app.project_file.path.as_os_str().is_empty(),
+                egui::Button::new("Import clip"),
+            )
+            .on_disabled_hover_text("Save the show first, clips are stored next to it")
+            .clicked()
+            && let Some(source) = rfd::FileDialog::new()
+                .add_filter("WAV audio", &["wav", "WAV"])
+                .pick_file()
+        {
+            let channel_idx = 0;
+            app.clip_import = Some(ClipImport::new(
+                source,
+                channel_idx,
+                free_slot(app, channel_idx),
+            ));
+        }
+    });
+}
+
+/// First clip index of a channel that has no clip in it.
+fn free_slot(app: &ClicksEditorApp, channel_idx: usize) -> usize {
+    (0..)
+        .find(|idx| !app.clip_manager.clips.contains_key(&(channel_idx, *idx)))
+        .unwrap_or_default()
+}
+
+/// Ask which slot to import a WAV file into, then convert it and write it into the show.
+pub fn import_prompt(app: &mut ClicksEditorApp, ctx: &egui::Context) {
+    let Some(mut import) = app.clip_import.clone() else {
+        return;
+    };
+    let taken = app
+        .clip_manager
+        .clips
+        .contains_key(&(import.channel_idx, import.clip_idx));
+
+    let mut apply = false;
+    let mut close = false;
+    egui::Modal::new(egui::Id::new("clip_import")).show(ctx, |ui| {
+        ui.heading("Import clip");
+        ui.label(
+            import
+                .source
+                .file_name()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        );
+        match &import.spec {
+            Ok(spec) => {
+                ui.label(format!(
+                    "{} Hz, {} bit, {} channel{}",
+                    spec.sample_rate,
+                    spec.bits_per_sample,
+                    spec.channels,
+                    if spec.channels == 1 { "" } else { "s" }
+                ));
+                if spec.sample_rate != ClipManager::SAMPLE_RATE
+                    || spec.bits_per_sample != ClipManager::BITS_PER_SAMPLE
+                    || spec.channels != 1
+                {
+                    ui.label(format!(
+                        "Will be converted to {} Hz, {} bit, 1 channel",
+                        ClipManager::SAMPLE_RATE,
+                        ClipManager::BITS_PER_SAMPLE
+                    ));
+                }
+            }
+            Err(err) => {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+        }
+        ui.separator();
+        egui::Grid::new("clip_import_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Channel:");
+                if ui
+                    .add(egui::DragValue::new(&mut import.channel_idx).range(0..=255))
+                    .changed()
+                {
+                    import.clip_idx = free_slot(app, import.channel_idx);
+                }
+                ui.end_row();
+                ui.label("Clip:");
+                ui.add(egui::DragValue::new(&mut import.clip_idx).range(0..=255));
+                ui.end_row();
+            });
+        if taken {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                "Replaces the clip already in this slot",
+            );
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(import.spec.is_ok(), egui::Button::new("Import"))
+                .clicked()
+            {
+                apply = true;
+            }
+            if ui.button("Cancel").clicked() {
+                close = true;
+            }
+        });
     });
+
+    if apply {
+        match app.clip_manager.add_file(
+            app.project_file.path.clone(),
+            &import.source,
+            import.channel_idx,
+            import.clip_idx,
+        ) {
+            Ok(path) => app.notifications.info(
+                "Clip imported",
+                format!(
+                    "Channel {}, clip {} at {}",
+                    import.channel_idx,
+                    import.clip_idx,
+                    path.display()
+                ),
+            ),
+            Err(err) => app.notifications.error("Could not import clip", err),
+        }
+        app.clip_import = None;
+    } else if close {
+        app.clip_import = None;
+    } else {
+        app.clip_import = Some(import);
+    }
 }
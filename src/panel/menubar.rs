@@ -1,4 +1,8 @@
-use crate::{actions, app::ClicksEditorApp};
+use crate::{
+    actions,
+    app::ClicksEditorApp,
+    tempomap::{Ramp, TempoUnit},
+};
 
 pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
     ui.horizontal_top(|ui| {
@@ -6,9 +10,56 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
             for act_id in actions::actions("project") {
                 actions::action(&act_id).button(app, ui);
             }
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Autosave every:");
+                ui.add(
+                    egui::DragValue::new(&mut app.autosave.interval)
+                        .speed(1)
+                        .max_decimals(0)
+                        .suffix(" s")
+                        .range(0..=3600),
+                )
+                .on_hover_text("0 turns autosave off");
+            });
             ui.label(app.project_file.show.metadata.name.str())
         });
         ui.menu_button("Edit", |ui| {
+            for act_id in actions::actions("edit") {
+                actions::action(&act_id).button(app, ui);
+            }
+            ui.horizontal(|ui| {
+                ui.label("History depth:");
+                if ui
+                    .add(
+                        egui::DragValue::new(&mut app.history.depth)
+                            .speed(1)
+                            .max_decimals(0)
+                            .suffix(" steps")
+                            .range(1..=10000),
+                    )
+                    .changed()
+                {
+                    app.history.trim();
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Tempo ramps:");
+                for (ramp, name) in [(Ramp::Linear, "Linear"), (Ramp::Exponential, "Exponential")] {
+                    ui.selectable_value(&mut app.tempo_ramp, ramp, name);
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Tempo in:");
+                for (unit, name) in [
+                    (TempoUnit::Click, "Clicks"),
+                    (TempoUnit::Quarter, "Quarters"),
+                    (TempoUnit::DottedQuarter, "Dotted quarters"),
+                ] {
+                    ui.selectable_value(&mut app.tempo_unit, unit, name);
+                }
+            });
+            ui.separator();
             for (name, category) in [
                 ("Show", "show"),
                 ("Cue", "cue"),
@@ -31,6 +82,9 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
             ui.label(format!("Editor version {}", ClicksEditorApp::VERSION));
             ui.label(format!("Common version {}", common::VERSION));
         });
+        if app.history.is_dirty() {
+            ui.label(egui::RichText::new("Modified").italics());
+        }
         if let Some(action) = &app.last_action {
             ui.label(action.name_global.clone());
         }
@@ -0,0 +1,153 @@
+use crate::{
+    app::ClicksEditorApp,
+    cueutils,
+    meter::{self, Meter},
+};
+
+/// Settings of the time signature dialog while it is open.
+#[derive(Debug, Clone, Copy)]
+pub struct MeterPrompt {
+    pub meter: Meter,
+    /// Tempo of a quarter note, which the beat lengths of the new bars are worked out from
+    pub quarter_bpm: f32,
+}
+
+const PRESETS: [(u8, u8); 8] = [
+    (2, 4),
+    (3, 4),
+    (4, 4),
+    (5, 4),
+    (6, 8),
+    (7, 8),
+    (9, 8),
+    (12, 8),
+];
+
+/// Ask for a time signature to set over the selected bars.
+pub fn display(app: &mut ClicksEditorApp, ctx: &egui::Context) {
+    let Some(mut prompt) = app.meter_prompt else {
+        return;
+    };
+    let Some(cue) = app.project_file.show.cues.get(app.selected_cue_idx) else {
+        app.meter_prompt = None;
+        return;
+    };
+    if cue.beats.is_empty() {
+        app.meter_prompt = None;
+        return;
+    }
+    let range = cueutils::measure_range(cue, app.selected_range());
+    let bars = {
+        let mut bars = cue.beats[range.clone()]
+            .iter()
+            .map(|b| b.bar_number)
+            .collect::<Vec<_>>();
+        bars.dedup();
+        bars.len()
+    };
+
+    let mut apply = false;
+    let mut close = false;
+    egui::Modal::new(egui::Id::new("meter_prompt")).show(ctx, |ui| {
+        ui.heading("Set time signature");
+        ui.label(format!(
+            "{} bar{} from bar {}",
+            bars,
+            if bars == 1 { "" } else { "s" },
+            cue.beats[range.start].bar_number
+        ));
+        ui.separator();
+        ui.horizontal_wrapped(|ui| {
+            for (numerator, denominator) in PRESETS {
+                let preset = Meter {
+                    subdivide: prompt.meter.subdivide,
+                    ..Meter::new(numerator, denominator)
+                };
+                ui.selectable_value(&mut prompt.meter, preset, preset.to_string());
+            }
+        });
+        egui::Grid::new("meter_prompt_grid")
+            .num_columns(2)
+            .show(ui, |ui| {
+                ui.label("Time signature:");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::DragValue::new(&mut prompt.meter.numerator)
+                            .speed(0.1)
+                            .range(1..=32),
+                    );
+                    ui.label("/");
+                    egui::ComboBox::from_id_salt("meter_denominator")
+                        .selected_text(prompt.meter.denominator.to_string())
+                        .width(40.0)
+                        .show_ui(ui, |ui| {
+                            for denominator in [1, 2, 4, 8, 16, 32] {
+                                ui.selectable_value(
+                                    &mut prompt.meter.denominator,
+                                    denominator,
+                                    denominator.to_string(),
+                                );
+                            }
+                        });
+                });
+                ui.end_row();
+                ui.label("Quarter note:");
+                ui.add(
+                    egui::DragValue::new(&mut prompt.quarter_bpm)
+                        .speed(0.5)
+                        .max_decimals(1)
+                        .range(10.0..=400.0)
+                        .suffix(" BPM"),
+                );
+                ui.end_row();
+                ui.label("Click every note:");
+                ui.checkbox(&mut prompt.meter.subdivide, "").on_hover_text(
+                    "Otherwise compound and odd meters click once per group of notes",
+                );
+                ui.end_row();
+                ui.label("Clicks per bar:");
+                ui.label(
+                    prompt
+                        .meter
+                        .groups()
+                        .iter()
+                        .map(|g| g.to_string())
+                        .collect::<Vec<_>>()
+                        .join("+"),
+                );
+                ui.end_row();
+            });
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Apply").clicked() {
+                apply = true;
+            }
+            if ui.button("Cancel").clicked() {
+                close = true;
+            }
+        });
+    });
+
+    if apply {
+        let quarter_length = (60_000_000.0 / prompt.quarter_bpm.max(1.0)) as u32;
+        let start = range.start;
+        let cue = &app.project_file.show.cues[app.selected_cue_idx];
+        let bars = cue.beats[range.start].bar_number as usize
+            ..=cue.beats[range.end - 1].bar_number as usize;
+        app.project_file
+            .meters_mut(app.selected_cue_idx)
+            .set(bars, prompt.meter);
+        meter::apply(
+            &mut app.project_file.show.cues[app.selected_cue_idx],
+            range,
+            prompt.meter,
+            quarter_length,
+        );
+        app.select_beat(start, false);
+        app.meter_prompt = None;
+    } else if close {
+        app.meter_prompt = None;
+    } else {
+        app.meter_prompt = Some(prompt);
+    }
+}
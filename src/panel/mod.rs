@@ -1,7 +1,13 @@
 pub mod beatlist;
+pub mod chase;
 pub mod cliplist;
 pub mod cuelist;
 pub mod menubar;
+pub mod meter;
+pub mod performance;
+pub mod problems;
 pub mod properties;
+pub mod recovery;
 pub mod timeline;
 pub mod toolbar;
+pub mod unsaved;
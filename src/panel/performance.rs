@@ -0,0 +1,207 @@
+use crate::{
+    app::ClicksEditorApp,
+    cueutils,
+    simulate::{Ending, Run, Simulation},
+    timecode::format_time,
+};
+use common::{cue::Cue, event::EventDescription};
+use egui::{Align2, Color32, FontId, Rect, Stroke, pos2, vec2};
+use std::{collections::HashMap, ops::Range};
+
+const LANE_HEIGHT: f32 = 15.0;
+const FONT: FontId = FontId::monospace(12.0);
+/// Space between passes, where the jump or pause that ends a pass is drawn
+const GAP: f32 = 18.0;
+
+fn ordinal(n: usize) -> String {
+    let suffix = match (n % 100, n % 10) {
+        (11..=13, _) => "th",
+        (_, 1) => "st",
+        (_, 2) => "nd",
+        (_, 3) => "rd",
+        _ => "th",
+    };
+    format!("{n}{suffix}")
+}
+
+/// Label every pass that plays beats which are played more than once, with which time round
+/// it is.
+fn pass_labels(run: &Run, passes: &[Range<usize>]) -> Vec<Option<String>> {
+    let mut total: HashMap<usize, usize> = HashMap::new();
+    for step in &run.steps {
+        *total.entry(step.beat_idx).or_default() += 1;
+    }
+    let mut seen: HashMap<usize, usize> = HashMap::new();
+    passes
+        .iter()
+        .map(|pass| {
+            let steps = &run.steps[pass.clone()];
+            let mut time = 0;
+            for step in steps {
+                let count = seen.entry(step.beat_idx).or_default();
+                *count += 1;
+                time = time.max(*count);
+            }
+            steps
+                .iter()
+                .any(|s| total[&s.beat_idx] > 1)
+                .then(|| format!("{} time", ordinal(time)))
+        })
+        .collect()
+}
+
+/// Draw the cue the way it will be played, with every repeat and volta taken in order.
+pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui, cue: &Cue) {
+    let run = Simulation::default().run(cue);
+    let passes = run.passes();
+    let labels = pass_labels(&run, &passes);
+    let (zoom, proportional) = (app.zoom, app.proportional_beat_length);
+    let beat_width = |beat_idx: usize| {
+        if proportional {
+            zoom * cue.beats[beat_idx].length as f32 / 500000.0
+        } else {
+            zoom
+        }
+    };
+
+    let mut rehearsal_marks: HashMap<usize, String> = HashMap::new();
+    for event in cueutils::events(cue) {
+        if let Some(EventDescription::RehearsalMarkEvent { label }) = event.event {
+            rehearsal_marks.insert(event.location as usize, label.str().to_string());
+        }
+    }
+
+    egui::ScrollArea::horizontal().show(ui, |ui| {
+        let width = run
+            .steps
+            .iter()
+            .map(|s| beat_width(s.beat_idx))
+            .sum::<f32>()
+            + GAP * passes.len() as f32;
+        let (rect, resp) =
+            ui.allocate_exact_size(vec2(width, LANE_HEIGHT * 4.0), egui::Sense::click());
+        let p = ui.painter();
+        let lane = |n: usize| rect.min.y + n as f32 * LANE_HEIGHT;
+
+        let mut x = rect.min.x;
+        let mut clicked = None;
+        for (pass_idx, pass) in passes.iter().enumerate() {
+            let pass_start = x;
+            for step_idx in pass.clone() {
+                let step = &run.steps[step_idx];
+                let beat = &cue.beats[step.beat_idx];
+                let cell = Rect::from_min_size(
+                    pos2(x, lane(1)),
+                    vec2(beat_width(step.beat_idx), LANE_HEIGHT * 3.0),
+                );
+                if step.beat_idx == app.selected_beat_idx {
+                    p.rect_filled(cell, 0.0, Color32::DARK_GREEN.gamma_multiply(0.5));
+                }
+                if ui.rect_contains_pointer(cell) {
+                    p.rect_filled(cell, 0.0, Color32::GRAY.gamma_multiply(0.2));
+                    if resp.clicked() {
+                        clicked = Some(step.beat_idx);
+                    }
+                }
+                p.line_segment(
+                    [cell.left_top(), cell.left_bottom()],
+                    Stroke::new(
+                        1.0,
+                        if beat.count == 1 {
+                            Color32::GRAY
+                        } else {
+                            Color32::DARK_GRAY
+                        },
+                    ),
+                );
+                if beat.count == 1 {
+                    p.text(
+                        pos2(x + 2.0, lane(1)),
+                        Align2::LEFT_TOP,
+                        beat.bar_number.to_string(),
+                        FONT,
+                        Color32::GRAY,
+                    );
+                }
+                if let Some(label) = rehearsal_marks.get(&step.beat_idx) {
+                    p.text(
+                        pos2(x + 2.0, lane(2)),
+                        Align2::LEFT_TOP,
+                        label,
+                        FONT,
+                        Color32::RED,
+                    );
+                }
+                x += cell.width();
+            }
+
+            // Pass header, with how many times round it is and how long it takes
+            let header = Rect::from_min_max(pos2(pass_start, lane(0)), pos2(x, lane(1)));
+            p.rect_stroke(
+                header.shrink(1.0),
+                2.0,
+                Stroke::new(1.0, Color32::YELLOW),
+                egui::StrokeKind::Inside,
+            );
+            let clipped = p.with_clip_rect(header);
+            clipped.text(
+                header.left_top() + vec2(3.0, 1.0),
+                Align2::LEFT_TOP,
+                format!(
+                    "{}{}",
+                    labels[pass_idx]
+                        .as_ref()
+                        .map_or(String::new(), |l| format!("{l} ")),
+                    format_time(run.duration(pass.clone()))
+                ),
+                FONT,
+                Color32::YELLOW,
+            );
+
+            // What ends the pass
+            let last = pass.end - 1;
+            let glyph = if run.pauses.iter().any(|p| p.step_idx == last) {
+                Some(egui_material_icons::icons::ICON_PAUSE)
+            } else if pass.end < run.steps.len() {
+                Some(egui_material_icons::icons::ICON_STEP_INTO)
+            } else if run.ending == Ending::Loop {
+                Some(egui_material_icons::icons::ICON_REPEAT)
+            } else {
+                None
+            };
+            if let Some(glyph) = glyph {
+                p.text(
+                    pos2(x + GAP / 2.0, rect.center().y),
+                    Align2::CENTER_CENTER,
+                    glyph,
+                    FontId::monospace(14.0),
+                    Color32::YELLOW,
+                );
+            }
+            x += GAP;
+        }
+
+        if let Some(beat_idx) = clicked {
+            app.select_beat(beat_idx, false);
+        }
+    });
+
+    ui.label(format!(
+        "{} beats in {} passes, {}",
+        run.steps.len(),
+        passes.len(),
+        format_time(run.length)
+    ));
+    match run.ending {
+        Ending::EndOfCue => {}
+        Ending::NextCue => {
+            ui.label("Then moves on to the next cue.");
+        }
+        Ending::Loop => {
+            ui.label("Then goes round the last pass until jump mode is changed.");
+        }
+        Ending::BeatLimit => {
+            ui.label(format!("Stopped after {} beats.", run.steps.len()));
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{
+    app::ClicksEditorApp,
+    validate::{self, Severity},
+};
+use egui::{Color32, vec2};
+
+pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
+    let problems = validate::validate_show(&app.project_file.show, Some(&app.clip_manager));
+    if problems.is_empty() {
+        ui.label("No problems found.");
+        return;
+    }
+
+    let height = 7.0;
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        egui::Grid::new("problemlist")
+            .striped(true)
+            .num_columns(4)
+            .spacing(vec2(10.0, height))
+            .show(ui, |ui| {
+                ui.label("");
+                ui.label("Cue");
+                ui.label("Beat");
+                ui.label("Problem");
+                ui.end_row();
+
+                for (i, problem) in problems.iter().enumerate() {
+                    let tl = ui.cursor().min;
+                    let (icon, col) = match problem.severity {
+                        Severity::Error => (egui_material_icons::icons::ICON_ERROR, Color32::RED),
+                        Severity::Warning => {
+                            (egui_material_icons::icons::ICON_WARNING, Color32::YELLOW)
+                        }
+                    };
+                    ui.label(egui::RichText::new(icon).color(col));
+                    ui.label(
+                        app.project_file.show.cues[problem.cue_idx]
+                            .metadata
+                            .human_ident
+                            .str(),
+                    );
+                    ui.label(
+                        problem
+                            .beat_idx
+                            .map(|idx| idx.to_string())
+                            .unwrap_or_default(),
+                    );
+                    ui.label(&problem.message);
+                    let br = ui.cursor().min + vec2(ui.available_width(), height);
+                    ui.end_row();
+
+                    let response = ui.interact(
+                        egui::Rect::from_min_max(tl, br),
+                        ui.make_persistent_id(("problem", i)),
+                        egui::Sense::click(),
+                    );
+
+                    if response.clicked() {
+                        app.selected_cue_idx = problem.cue_idx;
+                        app.select_beat(problem.beat_idx.unwrap_or(0), false);
+                    }
+                }
+            });
+    });
+}
@@ -1,4 +1,8 @@
-use crate::{actions, app::ClicksEditorApp};
+use crate::{
+    actions,
+    app::ClicksEditorApp,
+    timecode::{FrameRate, Timecode},
+};
 use common::{
     event::{
         Event, EventCursor, EventDescription, JumpModeChange, JumpRequirement, PauseEventBehaviour,
@@ -12,6 +16,7 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
         return;
     }
     let cue = &mut app.project_file.show.cues[app.selected_cue_idx];
+    let clips = &app.clip_manager;
 
     let mut recalculate_flag = false;
     egui::ScrollArea::horizontal().show(ui, |ui| {
@@ -77,18 +82,32 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
                         .show(ui, |ui| match &mut event.event {
                             Some(EventDescription::TempoChangeEvent { tempo }) => {
                                 ui.label("Tempo:");
-                                if ui
-                                    .add(
-                                        egui::DragValue::new(tempo)
-                                            .speed(0.4)
-                                            .max_decimals(0)
-                                            .suffix(" BPM")
-                                            .range(1..=500),
-                                    )
-                                    .is_pointer_button_down_on()
-                                {
-                                    recalculate_flag = true
-                                }
+                                ui.horizontal(|ui| {
+                                    if ui
+                                        .add(
+                                            egui::DragValue::new(tempo)
+                                                .speed(0.4)
+                                                .max_decimals(0)
+                                                .suffix(" BPM")
+                                                .range(1..=500),
+                                        )
+                                        .is_pointer_button_down_on()
+                                    {
+                                        recalculate_flag = true
+                                    }
+                                    if ui
+                                        .button("Tap")
+                                        .on_hover_text("Click along with the beat to set the tempo")
+                                        .clicked()
+                                        && let Some(bpm) = tap_tempo(
+                                            ui.ctx(),
+                                            egui::Id::new(("tap_tempo", event_idx)),
+                                        )
+                                    {
+                                        *tempo = bpm.round().clamp(1.0, 500.0) as _;
+                                        recalculate_flag = true
+                                    }
+                                });
                                 ui.end_row();
                             }
                             Some(EventDescription::GradualTempoChangeEvent {
@@ -147,13 +166,53 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
                                 ui.end_row();
                             }
                             Some(EventDescription::TimecodeEvent { time }) => {
+                                let rate = FrameRate::from_stored(time.frame_rate as u32);
+                                ui.label("Frame rate:");
+                                egui::ComboBox::from_id_salt(("frame rate box", event_idx))
+                                    .selected_text(rate.map_or_else(
+                                        || format!("Unknown ({} fps)", time.frame_rate),
+                                        |rate| rate.to_string(),
+                                    ))
+                                    .show_ui(ui, |ui| {
+                                        for val in FrameRate::ALL {
+                                            if ui
+                                                .selectable_label(
+                                                    Some(val) == rate,
+                                                    val.to_string(),
+                                                )
+                                                .clicked()
+                                            {
+                                                // Keep the same moment, counted at the new rate
+                                                *time = match Timecode::from_instant(time) {
+                                                    Some(kept) => Timecode {
+                                                        frame: (kept.to_us() as f64 * val.fps()
+                                                            / 1_000_000.0)
+                                                            .round()
+                                                            as i64,
+                                                        rate: val,
+                                                    },
+                                                    None => Timecode::from_hmsf(
+                                                        time.h as i64,
+                                                        time.m as i64,
+                                                        time.s as i64,
+                                                        0,
+                                                        val,
+                                                    ),
+                                                }
+                                                .to_instant();
+                                            }
+                                        }
+                                    });
+                                ui.end_row();
                                 ui.label("Time:");
+                                let max_frame =
+                                    rate.map_or(time.frame_rate as i64, |r| r.nominal() as i64) - 1;
                                 ui.horizontal(|ui| {
                                     for (val, max, unit) in [
-                                        (&mut time.h, 99, 'h'),
+                                        (&mut time.h, 23, 'h'),
                                         (&mut time.m, 59, 'm'),
                                         (&mut time.s, 59, 's'),
-                                        (&mut time.f, 99, 'f'),
+                                        (&mut time.f, max_frame, 'f'),
                                     ] {
                                         ui.add(
                                             egui::DragValue::new(val)
@@ -243,6 +302,27 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
                                 clip_idx,
                                 sample,
                             }) => {
+                                ui.label("Clip:");
+                                egui::ComboBox::from_id_salt(("clip box", event_idx))
+                                    .selected_text(
+                                        clips.label(*channel_idx as usize, *clip_idx as usize),
+                                    )
+                                    .show_ui(ui, |ui| {
+                                        for ((ch, cl), clip) in clips.sorted() {
+                                            if ui
+                                                .selectable_label(
+                                                    *ch == *channel_idx as usize
+                                                        && *cl == *clip_idx as usize,
+                                                    format!("{ch}.{cl} {}", clip.label()),
+                                                )
+                                                .clicked()
+                                            {
+                                                *channel_idx = *ch as _;
+                                                *clip_idx = *cl as _;
+                                            }
+                                        }
+                                    });
+                                ui.end_row();
                                 ui.label("Channel:");
                                 ui.add(
                                     egui::DragValue::new(channel_idx)
@@ -251,7 +331,7 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
                                         .range(0..=29),
                                 );
                                 ui.end_row();
-                                ui.label("Clip:");
+                                ui.label("Clip index:");
                                 ui.add(
                                     egui::DragValue::new(clip_idx)
                                         .speed(0.1)
@@ -313,3 +393,24 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
         (actions::action("cue:recalculate_tempo_changes").function)(app)
     }
 }
+
+/// Register a tap and return the tempo of the taps so far, once there are at least two.
+/// A pause of more than two seconds starts over.
+fn tap_tempo(ctx: &egui::Context, id: egui::Id) -> Option<f64> {
+    let now = ctx.input(|i| i.time);
+    let mut taps: Vec<f64> = ctx.data_mut(|d| d.get_temp(id)).unwrap_or_default();
+    if taps.last().is_some_and(|last| now - last > 2.0) {
+        taps.clear();
+    }
+    taps.push(now);
+    if taps.len() > 9 {
+        taps.remove(0);
+    }
+    ctx.data_mut(|d| d.insert_temp(id, taps.clone()));
+
+    let (first, last) = (taps.first()?, taps.last()?);
+    if taps.len() < 2 || last <= first {
+        return None;
+    }
+    Some(60.0 * (taps.len() - 1) as f64 / (last - first))
+}
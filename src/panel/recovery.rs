@@ -0,0 +1,43 @@
+use crate::{app::ClicksEditorApp, recovery};
+
+/// Offer to restore a recovery snapshot found at startup or when a show is loaded.
+pub fn display(app: &mut ClicksEditorApp, ctx: &egui::Context) {
+    let Some(path) = app.recovery_prompt.clone() else {
+        return;
+    };
+
+    let mut choice = None;
+    egui::Modal::new(egui::Id::new("recovery_prompt")).show(ctx, |ui| {
+        ui.heading("Recover unsaved changes");
+        ui.label("The editor was closed with unsaved changes.");
+        ui.label("A recovery snapshot newer than the saved show was found:");
+        ui.monospace(path.to_string_lossy());
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Restore").clicked() {
+                choice = Some(true);
+            }
+            if ui.button("Discard").clicked() {
+                choice = Some(false);
+            }
+        });
+    });
+
+    match choice {
+        Some(true) => {
+            app.recovery_prompt = None;
+            match recovery::restore(&path, &mut app.project_file) {
+                // Restoring is a single step, undo goes back to the saved show
+                Ok(()) => {
+                    app.history.commit(&app.project_file);
+                }
+                Err(err) => app.notifications.error("Recovery failed", err),
+            }
+        }
+        Some(false) => {
+            app.recovery_prompt = None;
+            let _ = std::fs::remove_file(path);
+        }
+        None => {}
+    }
+}
@@ -1,10 +1,22 @@
-use crate::{
-    app::ClicksEditorApp,
-    clip::{Clip, ClipManager},
+use crate::{app::ClicksEditorApp, clip::Clip, cueutils, timecode};
+use common::{
+    beat::Beat,
+    cue::Cue,
+    event::{EventCursor, EventDescription, JumpModeChange, JumpRequirement, PauseEventBehaviour},
 };
-use common::{beat::Beat, cue::Cue, event::{EventCursor, EventDescription, JumpModeChange, JumpRequirement}};
 use egui::{pos2, vec2, Align, Align2, Color32, FontId, Pos2, Rect, Response, Stroke};
 
+/// What dragging an event handle on the timeline changes.
+#[derive(Clone, Copy, Hash, PartialEq, Eq)]
+enum DragKind {
+    /// Move the event to another beat
+    Location,
+    /// Retarget a jump
+    Destination,
+    /// Change the length of a gradual tempo change
+    GradualEnd,
+}
+
 #[derive(Clone)]
 struct RunningClip {
     channel_idx: usize,
@@ -31,11 +43,19 @@ impl TimelineRenderer {
     const TEXT_SIZE: f32 = 12.0;
     const TEXT_BUMP: f32 = 12.0 * 0.2;
     const FONT: FontId = FontId::monospace(Self::TEXT_SIZE);
+    const CLIP_HEIGHT: f32 = Self::LANE_HEIGHT * 2.4;
+
+    // Lanes in the order display() draws them
+    const JUMP_LANE: usize = 0;
+    const TIMECODE_LANE: usize = 4;
+    const TEMPO_LANE: usize = 5;
+    const REHEARSAL_LANE: usize = 6;
+    const PLAYBACK_LANE: usize = 7;
 
     fn new(app: &mut ClicksEditorApp, ui: &mut egui::Ui, cue: Cue) -> Self {
         let (rect, resp) = ui.allocate_exact_size(
             vec2(app.zoom * cue.beats.len() as f32, ui.available_height()),
-            egui::Sense::click(),
+            egui::Sense::click_and_drag(),
         );
         let mut a = Self {
             rect,
@@ -86,13 +106,49 @@ impl TimelineRenderer {
         pos2(head, self.head.y)
     }
 
-    fn next_lane(&mut self) {
+    /// Horizontal position of a point in time, in microseconds from the start of the cue.
+    fn x_at_time(&self, time: u64) -> f32 {
+        let mut x = self.rect.min.x;
+        let mut time_head: u64 = 0;
+        for beat in &self.cue.beats {
+            let width = self.beat_width_from_length(beat.length);
+            if time < time_head + beat.length as u64 {
+                return x + width * (time - time_head) as f32 / beat.length.max(1) as f32;
+            }
+            x += width;
+            time_head += beat.length as u64;
+        }
+        x
+    }
+
+    /// Index of the beat under a horizontal position.
+    fn beat_at_x(&self, x: f32) -> Option<usize> {
+        let mut head = self.rect.min.x;
+        for (i, beat) in self.cue.beats.iter().enumerate() {
+            head += self.beat_width_from_length(beat.length);
+            if x < head {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn lane_top(&self, lane: usize) -> f32 {
+        self.rect.min.y + lane as f32 * (Self::LANE_HEIGHT + Self::LANE_BUFFER)
+    }
+
+    /// Go back to before the first beat, to walk the same lane again
+    fn rewind(&mut self) {
         self.head.x = self.rect.min.x - self.base_beat_width;
         self.time_head = 0 - self.cue.get_beat(0).unwrap_or_default().length as i64;
-        self.head.y += Self::LANE_HEIGHT + Self::LANE_BUFFER;
         self.beat_idx = -1;
     }
 
+    fn next_lane(&mut self) {
+        self.rewind();
+        self.head.y += Self::LANE_HEIGHT + Self::LANE_BUFFER;
+    }
+
     fn next_beat(&mut self) -> Option<Beat> {
         self.head.x += self.beat_width();
         self.beat_idx += 1;
@@ -117,9 +173,91 @@ impl TimelineRenderer {
         }
     }
 
+    /// Time signatures set on the bars of the cue, wherever they change
+    fn meters(&mut self, app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
+        self.blockout_lane(app, ui);
+        let p = ui.painter();
+        let mut bars = app
+            .project_file
+            .meters(app.selected_cue_idx)
+            .bars(&self.cue)
+            .into_iter()
+            .peekable();
+        // Where the last bar with a known time signature ends, and its time signature
+        let mut last = None;
+        while self.next_beat().is_some() {
+            let idx = self.beat_idx as usize;
+            let Some((range, meter)) = bars.next_if(|(range, _)| range.start == idx) else {
+                continue;
+            };
+            if last != Some((idx, meter)) {
+                p.text(
+                    self.head_text(),
+                    Align2::LEFT_TOP,
+                    meter.to_string(),
+                    Self::FONT,
+                    Color32::LIGHT_BLUE,
+                );
+            }
+            last = Some((range.end, meter));
+        }
+    }
+
+    /// Clock time at downbeats with room for it, from the first time they are played
+    fn time_ruler(&mut self, app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
+        self.blockout_lane(app, ui);
+        let p = ui.painter();
+        let revision = app.history.revision();
+        let beat_times = app
+            .beat_times
+            .get(revision, app.selected_cue_idx, &self.cue);
+        let mut free_from = f32::MIN;
+        while let Some(beat) = self.next_beat() {
+            if beat.count != 1 || self.head.x < free_from {
+                continue;
+            }
+            let Some(time) = beat_times.times[self.beat_idx as usize] else {
+                continue;
+            };
+            let text = timecode::format_time(time);
+            free_from = self.head.x + (text.len() as f32 + 1.0) * Self::TEXT_SIZE * 0.6;
+            p.text(
+                self.head_text(),
+                Align2::LEFT_TOP,
+                text,
+                Self::FONT,
+                Color32::GRAY,
+            );
+        }
+    }
+
+    /// Timecode events, and the timecode running at downbeats in between
     fn timecode(&mut self, app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
         self.blockout_lane(app, ui);
         let p = ui.painter();
+        let revision = app.history.revision();
+        let beat_times = app
+            .beat_times
+            .get(revision, app.selected_cue_idx, &self.cue);
+        let mut free_from = f32::MIN;
+        while let Some(beat) = self.next_beat() {
+            if beat.count == 1
+                && self.head.x >= free_from
+                && let Some(tc) = beat_times.timecodes[self.beat_idx as usize]
+            {
+                let text = tc.to_string();
+                free_from = self.head.x + (text.len() as f32 + 1.0) * Self::TEXT_SIZE * 0.6;
+                p.text(
+                    self.head_text(),
+                    Align2::LEFT_TOP,
+                    text,
+                    Self::FONT,
+                    Color32::DARK_GREEN,
+                );
+            }
+        }
+
+        self.rewind();
         let events = self.cue.events.clone();
         let mut cursor = EventCursor::new(&events);
         while let Some(_beat) = self.next_beat() {
@@ -176,9 +314,8 @@ impl TimelineRenderer {
                     );
                     let mut line_length = 0.0;
                     //line_length -= Self::TEXT_SIZE * 2.5;
-                    for beat_forward in
-                        &app.project_file.show.cues[app.selected_cue_idx].beats[i..i + length as usize]
-                    {
+                    let end = (i + length as usize).min(self.cue.beats.len());
+                    for beat_forward in &self.cue.beats[i..end] {
                         line_length += self.beat_width_from_length(beat_forward.length);
                     }
                     p.line_segment(
@@ -293,15 +430,15 @@ impl TimelineRenderer {
     }
 
     fn playbacks(&mut self, app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
-        let clip_height = Self::LANE_HEIGHT * 2.4;
+        let clip_height = Self::CLIP_HEIGHT;
 
         let p = ui.painter();
         let events = self.cue.events.clone();
         let mut cursor = EventCursor::new(&events);
         while let Some(beat) = self.next_beat() {
-            while cursor.at_or_before(self.beat_idx as u16) && let Some(event) = cursor.get_next() {
             // Events, i.e. playback start or playback stop get triggered once at the beat they
             // occur
+            while cursor.at_or_before(self.beat_idx as u16) && let Some(event) = cursor.get_next() {
                 if let Some(EventDescription::PlaybackEvent {
                     channel_idx,
                     clip_idx,
@@ -321,6 +458,14 @@ impl TimelineRenderer {
                         ],
                         Stroke::new(3.0, Color32::GREEN),
                     );
+                    p.text(
+                        self.head + vec2(4.0, channel_idx as f32 * clip_height + 1.0),
+                        Align2::LEFT_TOP,
+                        app.clip_manager
+                            .label(channel_idx as usize, clip_idx as usize),
+                        FontId::proportional(10.0),
+                        Color32::WHITE,
+                    );
                 } else if let Some(EventDescription::PlaybackStopEvent {
                     channel_idx: stop_channel_idx,
                 }) = event.event
@@ -350,43 +495,39 @@ impl TimelineRenderer {
                     Color32::BLUE.gamma_multiply(0.5),
                 );
 
-                // Waveform
+                // Waveform, one line per pixel from the lowest to the highest sample under it
+                let Some(file) = app.clip_manager.clips.get(&(clip.channel_idx, clip.clip_idx))
+                else {
+                    continue;
+                };
+                let Some(peaks) = &file.peaks else {
+                    continue;
+                };
+                let gain = 1.0 / peaks.max_abs().max(f32::EPSILON);
+                // Playback positions count 48 kHz samples, whatever the rate of the file
+                let to_frames = |samples: i64| samples * file.sample_rate as i64 / 48000;
                 let sample_head =
                     self.time_head * 48 / 1000 - clip.sample_offset_from_start + clip.sample as i64;
-                let sample_len = beat.length as i64 * 48 / 1000;
-                let start_bucket = sample_head / ClipManager::PEAK_BUCKET_SIZE as i64;
-                let end_bucket = (sample_head + sample_len) / ClipManager::PEAK_BUCKET_SIZE as i64;
-                let bucket_width =
-                    beat_width / (sample_len / ClipManager::PEAK_BUCKET_SIZE as i64) as f32;
-                for bucket_idx in start_bucket..end_bucket {
-                    let bucket_head = self.head
-                        + vec2(
-                            bucket_width * (bucket_idx - start_bucket) as f32,
-                            clip.channel_idx as f32 * clip_height,
-                        );
-
-                    let bucket_val = match app
-                        .clip_manager
-                        .clips
-                        .get(&(clip.channel_idx, clip.clip_idx))
-                    {
-                        Some(clip) => {
-                            if (bucket_idx as usize) < clip.peak_buckets.len() {
-                                clip.peak_buckets[bucket_idx as usize]
-                            } else {
-                                0.0
-                            }
-                        }
-                        None => 0.0,
+                let frame_head = to_frames(sample_head);
+                let frame_len = to_frames(beat.length as i64 * 48 / 1000);
+                let columns = beat_width.ceil().max(1.0) as i64;
+                let center_y = clip.channel_idx as f32 * clip_height + clip_height / 2.0;
+                for column in 0..columns {
+                    let start = frame_head + frame_len * column / columns;
+                    let end = frame_head + frame_len * (column + 1) / columns;
+                    if end <= 0 {
+                        continue;
+                    }
+                    let Some((min, max)) = peaks.range(start.max(0) as usize..end as usize) else {
+                        continue;
                     };
-                    let center_y = vec2(0.0, clip_height / 2.0);
-                    let height_push = clip_height / 2.0 * bucket_val.abs();
+                    let x = column as f32 + 0.5;
                     p.line_segment(
                         [
-                            bucket_head + center_y - vec2(0.0, height_push),
-                            bucket_head + center_y + vec2(0.0, height_push),
+                            self.head + vec2(x, center_y - clip_height / 2.0 * max * gain),
+                            self.head + vec2(x, center_y - clip_height / 2.0 * min * gain),
                         ],
-                        Stroke::new(bucket_width.ceil(), Color32::WHITE),
+                        Stroke::new(1.0, Color32::WHITE),
                     );
                 }
             }
@@ -394,7 +535,29 @@ impl TimelineRenderer {
     }
 
     fn background(&mut self, app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
+        // Click selects a beat, shift-click or dragging selects a range
+        let shift = ui.input(|i| i.modifiers.shift);
+        if self.resp.drag_started()
+            && let Some(origin) = ui.input(|i| i.pointer.press_origin())
+            && let Some(idx) = self.beat_at_x(origin.x)
+        {
+            app.select_beat(idx, shift);
+        }
+        if self.resp.dragged()
+            && let Some(pos) = self.resp.interact_pointer_pos()
+            && let Some(idx) = self.beat_at_x(pos.x)
+        {
+            app.select_beat(idx, true);
+        }
+        if self.resp.clicked()
+            && let Some(pos) = self.resp.interact_pointer_pos()
+            && let Some(idx) = self.beat_at_x(pos.x)
+        {
+            app.select_beat(idx, shift);
+        }
+
         let sel_beat = self.cue.beats[app.selected_beat_idx].clone();
+        let sel_range = app.selected_range();
         let p = ui.painter();
 
         let mut beat_rect = Rect::from_min_max(self.rect.min, self.rect.min);
@@ -410,9 +573,15 @@ impl TimelineRenderer {
                 p.rect_filled(beat_rect, 0.0, Color32::DARK_GREEN);
             }
 
+            // Selected range marker
+            if app.has_range() && sel_range.contains(&i) {
+                p.rect_filled(beat_rect, 0.0, Color32::DARK_GREEN.gamma_multiply(0.75));
+            }
             // Selected measure marker
-            if beat.bar_number == sel_beat.bar_number {
-                ui.scroll_to_rect(beat_rect, None);
+            else if beat.bar_number == sel_beat.bar_number {
+                if !self.resp.dragged() {
+                    ui.scroll_to_rect(beat_rect, None);
+                }
                 p.rect_filled(beat_rect, 0.0, Color32::DARK_GREEN.gamma_multiply(0.5));
             }
 
@@ -424,9 +593,6 @@ impl TimelineRenderer {
             // Hovered beat marker
             if ui.rect_contains_pointer(beat_rect) {
                 p.rect_filled(beat_rect, 0.0, Color32::GRAY.gamma_multiply(0.2));
-                if self.resp.clicked() {
-                    app.selected_beat_idx = i;
-                }
             }
 
             // Downbeat line and text
@@ -447,6 +613,165 @@ impl TimelineRenderer {
         }
     }
 
+    /// Let events be dragged to another beat, jump destinations be retargeted and gradual tempo
+    /// changes be stretched. The change is made when the drag is released.
+    fn event_handles(&mut self, app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
+        let handle_width = Self::TEXT_SIZE * 1.5;
+        let mut dropped = None;
+        for (event_idx, event) in cueutils::events(&self.cue).iter().enumerate() {
+            let location = event.location as usize;
+            let jump_lane = (self.lane_top(Self::JUMP_LANE), Self::LANE_HEIGHT);
+            let tempo_lane = (self.lane_top(Self::TEMPO_LANE), Self::LANE_HEIGHT);
+            // Clip channels are drawn as rows inside the playback lane
+            let channel_row = |channel_idx: usize| {
+                (
+                    self.lane_top(Self::PLAYBACK_LANE) + channel_idx as f32 * Self::CLIP_HEIGHT,
+                    Self::CLIP_HEIGHT,
+                )
+            };
+            let mut handles = vec![];
+            match event.event {
+                Some(EventDescription::JumpEvent { destination, .. }) => {
+                    handles.push((DragKind::Location, location, jump_lane));
+                    handles.push((DragKind::Destination, destination as usize, jump_lane));
+                }
+                Some(EventDescription::PauseEvent { behaviour }) => {
+                    handles.push((DragKind::Location, location, jump_lane));
+                    if let PauseEventBehaviour::Jump { destination } = behaviour {
+                        handles.push((DragKind::Destination, destination as usize, jump_lane));
+                    }
+                }
+                Some(EventDescription::TimecodeEvent { .. }) => {
+                    handles.push((
+                        DragKind::Location,
+                        location,
+                        (self.lane_top(Self::TIMECODE_LANE), Self::LANE_HEIGHT),
+                    ));
+                }
+                Some(EventDescription::TempoChangeEvent { .. }) => {
+                    handles.push((DragKind::Location, location, tempo_lane));
+                }
+                Some(EventDescription::GradualTempoChangeEvent { length, .. }) => {
+                    handles.push((DragKind::Location, location, tempo_lane));
+                    handles.push((DragKind::GradualEnd, location + length as usize, tempo_lane));
+                }
+                Some(EventDescription::RehearsalMarkEvent { .. }) => {
+                    handles.push((
+                        DragKind::Location,
+                        location,
+                        (self.lane_top(Self::REHEARSAL_LANE), Self::LANE_HEIGHT),
+                    ));
+                }
+                Some(EventDescription::PlaybackEvent { channel_idx, .. }) => {
+                    handles.push((DragKind::Location, location, channel_row(channel_idx as usize)));
+                }
+                Some(EventDescription::PlaybackStopEvent { channel_idx }) => {
+                    handles.push((DragKind::Location, location, channel_row(channel_idx as usize)));
+                }
+                _ => {}
+            }
+
+            for (kind, beat_idx, (top, height)) in handles {
+                let x = self.head_at_idx(beat_idx.min(self.cue.beats.len())).x;
+                let rect = Rect::from_min_size(pos2(x, top), vec2(handle_width, height));
+                let resp = ui
+                    .interact(
+                        rect,
+                        egui::Id::new(("timeline_event", app.selected_cue_idx, event_idx, kind)),
+                        egui::Sense::drag(),
+                    )
+                    .on_hover_cursor(egui::CursorIcon::Grab);
+
+                if resp.dragged() {
+                    ui.ctx().set_cursor_icon(egui::CursorIcon::Grabbing);
+                    if let Some(pos) = resp.interact_pointer_pos()
+                        && let Some(target) = self.beat_at_x(pos.x)
+                    {
+                        let target_x = self.head_at_idx(target).x;
+                        let p = ui.painter();
+                        p.rect_stroke(
+                            rect,
+                            0.0,
+                            Stroke::new(1.0, Color32::WHITE),
+                            egui::StrokeKind::Inside,
+                        );
+                        p.rect_filled(
+                            Rect::from_min_size(
+                                pos2(target_x, top),
+                                vec2(self.beat_width_from_length(self.cue.beats[target].length), height),
+                            ),
+                            0.0,
+                            Color32::WHITE.gamma_multiply(0.3),
+                        );
+                        p.line_segment(
+                            [pos2(target_x, self.rect.min.y), pos2(target_x, self.rect.max.y)],
+                            Stroke::new(1.0, Color32::WHITE),
+                        );
+                    }
+                }
+                if resp.drag_stopped()
+                    && let Some(pos) = resp
+                        .interact_pointer_pos()
+                        .or(ui.input(|i| i.pointer.latest_pos()))
+                    && let Some(target) = self.beat_at_x(pos.x)
+                {
+                    dropped = Some((event_idx, kind, target));
+                }
+            }
+        }
+
+        let Some((event_idx, kind, target)) = dropped else {
+            return;
+        };
+        let cue = &mut app.project_file.show.cues[app.selected_cue_idx];
+        let mut events = cueutils::events(cue);
+        let Some(event) = events.get_mut(event_idx) else {
+            return;
+        };
+        match kind {
+            DragKind::Location => event.location = target as u16,
+            DragKind::Destination => cueutils::remap_destination(event, |_| target),
+            DragKind::GradualEnd => {
+                if let Some(EventDescription::GradualTempoChangeEvent { length, .. }) =
+                    &mut event.event
+                {
+                    *length = target.saturating_sub(event.location as usize).max(1) as _;
+                }
+            }
+        }
+        let is_tempo = matches!(
+            event.event,
+            Some(EventDescription::TempoChangeEvent { .. })
+                | Some(EventDescription::GradualTempoChangeEvent { .. })
+        );
+        // Keep the events in beat order, as they are when added one by one
+        events.sort_by_key(|e| e.location);
+        cueutils::set_events(cue, events);
+        if is_tempo {
+            cue.recalculate_tempo_changes();
+        }
+        if kind == DragKind::Location {
+            app.select_beat(target, false);
+        }
+    }
+
+    fn play_cursor(&mut self, app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
+        if app.preview.cue_idx != app.selected_cue_idx {
+            return;
+        }
+        let Some(time) = app.preview.position_us() else {
+            return;
+        };
+        let x = self.x_at_time(time);
+        ui.painter().line_segment(
+            [pos2(x, self.rect.min.y), pos2(x, self.rect.max.y)],
+            Stroke::new(2.0, Color32::WHITE),
+        );
+        if app.preview.is_playing() {
+            ui.scroll_to_rect(Rect::from_x_y_ranges(x..=x, self.rect.y_range()), None);
+        }
+    }
+
     fn blockout_lane(&mut self, app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
         let p = ui.painter();
 
@@ -473,6 +798,11 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
         return;
     }
 
+    if app.performance_view {
+        crate::panel::performance::display(app, ui, &cue);
+        return;
+    }
+
     egui::ScrollArea::horizontal().show(ui, |ui| {
         let mut tlr = TimelineRenderer::new(app, ui, cue);
         tlr.background(app, ui);
@@ -481,6 +811,10 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
         tlr.next_lane();
         tlr.bar_numbers(app, ui);
         tlr.next_lane();
+        tlr.meters(app, ui);
+        tlr.next_lane();
+        tlr.time_ruler(app, ui);
+        tlr.next_lane();
         tlr.timecode(app, ui);
         tlr.next_lane();
         tlr.tempo(app, ui);
@@ -488,6 +822,8 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
         tlr.rehearsal_marks(app, ui);
         tlr.next_lane();
         tlr.playbacks(app, ui);
+        tlr.event_handles(app, ui);
+        tlr.play_cursor(app, ui);
 
         tlr.try_zoom(app, ui);
     });
@@ -10,7 +10,7 @@ pub fn display(app: &mut ClicksEditorApp, ui: &mut egui::Ui) {
     }
 
     ui.horizontal(|ui| {
-        for category in ["cue", "view", "beat", "reload"] {
+        for category in ["cue", "view", "beat", "reload", "transport"] {
             for action_id in actions::actions(category) {
                 let action = actions::action(&action_id);
                 if action.button_icon(app, ui) {
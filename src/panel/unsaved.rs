@@ -0,0 +1,72 @@
+use crate::{app::ClicksEditorApp, recovery};
+
+/// What to do once the user has dealt with unsaved changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnsavedContinuation {
+    Close,
+    Load,
+    ImportJson,
+}
+
+impl UnsavedContinuation {
+    fn run(&self, app: &mut ClicksEditorApp, ctx: &egui::Context) {
+        match self {
+            Self::Close => {
+                app.close_confirmed = true;
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+            Self::Load => crate::io::load(app),
+            Self::ImportJson => crate::io::import_json(app),
+        }
+    }
+}
+
+/// Ask the user to save before throwing away unsaved changes, if anything is waiting for that.
+pub fn display(app: &mut ClicksEditorApp, ctx: &egui::Context) {
+    let Some(continuation) = app.unsaved_prompt else {
+        return;
+    };
+
+    let mut choice = None;
+    egui::Modal::new(egui::Id::new("unsaved_prompt")).show(ctx, |ui| {
+        ui.heading("Unsaved changes");
+        ui.label(format!(
+            "Do you want to save the changes to {}?",
+            if app.project_file.show.metadata.name.str().is_empty() {
+                "this show"
+            } else {
+                app.project_file.show.metadata.name.str()
+            }
+        ));
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Save").clicked() {
+                choice = Some(true);
+            }
+            if ui.button("Discard").clicked() {
+                choice = Some(false);
+            }
+            if ui.button("Cancel").clicked() {
+                app.unsaved_prompt = None;
+            }
+        });
+    });
+
+    match choice {
+        Some(true) => {
+            crate::io::save(app);
+            // Saving can fail or the save dialog can be cancelled, in which case nothing is lost
+            if !app.history.is_dirty() {
+                app.unsaved_prompt = None;
+                continuation.run(app, ctx);
+            }
+        }
+        Some(false) => {
+            // The changes are gone for good, so don't offer them back on the next start
+            recovery::remove(&app.project_file.path);
+            app.unsaved_prompt = None;
+            continuation.run(app, ctx);
+        }
+        None => {}
+    }
+}
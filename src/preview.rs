@@ -0,0 +1,446 @@
+use crate::{
+    click::ClickRenderer,
+    clip::{Clip, ClipManager},
+};
+use common::{
+    cue::Cue,
+    event::{EventCursor, EventDescription},
+};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+};
+
+/// A mixed down cue, and how far into it playback has come. Shared between the UI and the audio
+/// callback, which is why everything that changes during playback is atomic.
+pub struct Transport {
+    pub sample_rate: u32,
+    buffer: Vec<f32>,
+    position: AtomicUsize,
+    playing: AtomicBool,
+}
+
+impl Transport {
+    pub fn new(buffer: Vec<f32>, sample_rate: u32, start: usize) -> Self {
+        Self {
+            sample_rate,
+            buffer,
+            position: AtomicUsize::new(start),
+            playing: AtomicBool::new(true),
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing.load(Ordering::Relaxed)
+    }
+
+    pub fn set_playing(&self, playing: bool) {
+        self.playing.store(playing, Ordering::Relaxed)
+    }
+
+    pub fn position(&self) -> usize {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// Length of the mixed down cue, in samples.
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Playback position, in microseconds from the start of the cue.
+    pub fn position_us(&self) -> u64 {
+        self.position() as u64 * 1_000_000 / self.sample_rate as u64
+    }
+
+    /// Fill an interleaved output buffer with `channels` channels, advancing the playback
+    /// position. Outputs silence when paused or finished.
+    pub fn fill(&self, out: &mut [f32], channels: usize) {
+        let channels = channels.max(1);
+        let mut pos = self.position();
+        for frame in out.chunks_mut(channels) {
+            let sample = if self.is_playing() && pos < self.buffer.len() {
+                pos += 1;
+                self.buffer[pos - 1].clamp(-1.0, 1.0)
+            } else {
+                0.0
+            };
+            frame.fill(sample);
+        }
+        if pos >= self.buffer.len() {
+            self.set_playing(false);
+        }
+        self.position.store(pos, Ordering::Relaxed);
+    }
+}
+
+pub trait AudioBackend {
+    fn sample_rate(&self) -> u32;
+    fn start(&mut self, transport: Arc<Transport>) -> Result<(), String>;
+    fn stop(&mut self);
+    /// An error that came up while playing, if there is one that hasn't been taken yet.
+    fn take_error(&mut self) -> Option<String> {
+        None
+    }
+}
+
+/// Plays through the default output device of the system.
+#[derive(Default)]
+pub struct CpalBackend {
+    stream: Option<cpal::Stream>,
+    /// Errors of the stream, which are reported on the audio thread
+    errors: Option<mpsc::Receiver<String>>,
+}
+
+impl AudioBackend for CpalBackend {
+    fn sample_rate(&self) -> u32 {
+        cpal::default_host()
+            .default_output_device()
+            .and_then(|device| device.default_output_config().ok())
+            .map(|config| config.sample_rate().0)
+            .unwrap_or(48000)
+    }
+
+    fn start(&mut self, transport: Arc<Transport>) -> Result<(), String> {
+        self.stop();
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("No audio output device found")?;
+        let config = device
+            .default_output_config()
+            .map_err(|e| e.to_string())?
+            .config();
+        let channels = config.channels as usize;
+        let (sender, receiver) = mpsc::channel();
+        let stream = device
+            .build_output_stream(
+                &config,
+                move |data: &mut [f32], _| transport.fill(data, channels),
+                move |err| {
+                    let _ = sender.send(err.to_string());
+                },
+                None,
+            )
+            .map_err(|e| e.to_string())?;
+        stream.play().map_err(|e| e.to_string())?;
+        self.stream = Some(stream);
+        self.errors = Some(receiver);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.stream = None;
+        self.errors = None;
+    }
+
+    fn take_error(&mut self) -> Option<String> {
+        self.errors.as_ref()?.try_recv().ok()
+    }
+}
+
+/// Renders into a buffer instead of a sound card, for tests and machines without audio.
+pub struct OfflineBackend {
+    pub sample_rate: u32,
+    pub channels: usize,
+    pub output: Vec<f32>,
+    transport: Option<Arc<Transport>>,
+}
+
+impl OfflineBackend {
+    pub fn new(sample_rate: u32, channels: usize) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            output: vec![],
+            transport: None,
+        }
+    }
+
+    /// Pull `frames` frames from the transport into `output`, like an audio callback would.
+    pub fn process(&mut self, frames: usize) {
+        let Some(transport) = &self.transport else {
+            return;
+        };
+        let mut block = vec![0.0; frames * self.channels];
+        transport.fill(&mut block, self.channels);
+        self.output.extend(block);
+    }
+}
+
+impl AudioBackend for OfflineBackend {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn start(&mut self, transport: Arc<Transport>) -> Result<(), String> {
+        self.transport = Some(transport);
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.transport = None;
+    }
+}
+
+struct ScheduledClip {
+    channel_idx: usize,
+    clip_idx: usize,
+    /// First sample of the clip to play
+    sample: i64,
+    /// Cue time the clip starts and stops, in microseconds
+    start: u64,
+    end: u64,
+}
+
+/// Mix the click and all audio clips of a cue into a mono buffer at `sample_rate`. `clips` are the
+/// files of the clips by channel and clip index.
+pub fn render_cue(
+    cue: &Cue,
+    clips: &HashMap<(usize, usize), PathBuf>,
+    sample_rate: u32,
+) -> Vec<f32> {
+    let clicks = ClickRenderer {
+        sample_rate,
+        ..Default::default()
+    };
+    let mut buf = clicks.render_cue(cue);
+    let cue_end = ClickRenderer::cue_length(cue);
+
+    // Work out when each clip starts and stops. Only one clip plays per channel at a time, the
+    // same way the host does it.
+    let mut scheduled: Vec<ScheduledClip> = vec![];
+    let mut running: HashMap<usize, usize> = HashMap::new();
+    let events = cue.events.clone();
+    let mut cursor = EventCursor::new(&events);
+    let mut time_head: u64 = 0;
+    for (beat_idx, beat) in cue.beats.iter().enumerate() {
+        while cursor.at_or_before(beat_idx as u16)
+            && let Some(event) = cursor.get_next()
+        {
+            match event.event {
+                Some(EventDescription::PlaybackEvent {
+                    channel_idx,
+                    clip_idx,
+                    sample,
+                }) => {
+                    if let Some(idx) = running.remove(&(channel_idx as usize)) {
+                        scheduled[idx].end = time_head;
+                    }
+                    running.insert(channel_idx as usize, scheduled.len());
+                    scheduled.push(ScheduledClip {
+                        channel_idx: channel_idx as usize,
+                        clip_idx: clip_idx as usize,
+                        sample: sample as i64,
+                        start: time_head,
+                        end: cue_end,
+                    });
+                }
+                Some(EventDescription::PlaybackStopEvent { channel_idx }) => {
+                    if let Some(idx) = running.remove(&(channel_idx as usize)) {
+                        scheduled[idx].end = time_head;
+                    }
+                }
+                _ => {}
+            }
+        }
+        time_head += beat.length as u64;
+    }
+
+    let mut decoded: HashMap<(usize, usize), Option<(Vec<f32>, u32)>> = HashMap::new();
+    for sched in scheduled {
+        let samples = decoded
+            .entry((sched.channel_idx, sched.clip_idx))
+            .or_insert_with(|| {
+                let path = clips.get(&(sched.channel_idx, sched.clip_idx))?;
+                let (samples, spec) = Clip::read_mono(path.clone()).ok()?;
+                Some((samples, spec.sample_rate))
+            });
+        let Some((samples, clip_rate)) = samples else {
+            continue;
+        };
+
+        let start = (sched.start * sample_rate as u64 / 1_000_000) as usize;
+        let end = ((sched.end * sample_rate as u64 / 1_000_000) as usize).min(buf.len());
+        // The start sample of playback events is counted at the rate the host plays at
+        let first = sched.sample * *clip_rate as i64 / ClipManager::SAMPLE_RATE as i64;
+        for (i, out) in buf.iter_mut().enumerate().take(end).skip(start) {
+            // Nearest sample is good enough for previewing
+            let clip_pos =
+                first + ((i - start) as u64 * *clip_rate as u64 / sample_rate as u64) as i64;
+            if clip_pos < 0 {
+                continue;
+            }
+            match samples.get(clip_pos as usize) {
+                Some(sample) => *out += sample,
+                None => break,
+            }
+        }
+    }
+
+    buf
+}
+
+/// A cue being mixed down on a background thread, to be played once it is done.
+struct Rendering {
+    buffer: mpsc::Receiver<Vec<f32>>,
+    sample_rate: u32,
+    /// Sample to start playing from
+    start: usize,
+}
+
+/// Local playback of the selected cue, so it can be heard without the host.
+pub struct Preview {
+    pub backend: Box<dyn AudioBackend>,
+    pub transport: Option<Arc<Transport>>,
+    pub cue_idx: usize,
+    rendering: Option<Rendering>,
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Self {
+            backend: Box::new(CpalBackend::default()),
+            transport: None,
+            cue_idx: 0,
+            rendering: None,
+        }
+    }
+}
+
+impl Preview {
+    /// Start mixing down `cue` in the background, to be played from `start_us` microseconds in.
+    /// Decoding the clips can take a while, so playback only starts in [`Preview::poll`] once
+    /// it is done.
+    pub fn play(&mut self, cue: &Cue, cue_idx: usize, clip_manager: &ClipManager, start_us: u64) {
+        self.stop();
+        let sample_rate = self.backend.sample_rate();
+        let cue = cue.clone();
+        let clips: HashMap<(usize, usize), PathBuf> = clip_manager
+            .clips
+            .iter()
+            .map(|(key, clip)| (*key, clip.path.clone()))
+            .collect();
+        let (sender, receiver) = mpsc::channel();
+        // Dropping the receiver, by stopping or playing something else, drops the result
+        std::thread::spawn(move || {
+            let _ = sender.send(render_cue(&cue, &clips, sample_rate));
+        });
+        self.rendering = Some(Rendering {
+            buffer: receiver,
+            sample_rate,
+            start: (start_us * sample_rate as u64 / 1_000_000) as usize,
+        });
+        self.cue_idx = cue_idx;
+    }
+
+    /// Start playing a cue that is done rendering, and pass on errors of the audio backend.
+    pub fn poll(&mut self) -> Result<(), String> {
+        if let Some(rendering) = &self.rendering {
+            match rendering.buffer.try_recv() {
+                Ok(buf) => {
+                    let transport =
+                        Arc::new(Transport::new(buf, rendering.sample_rate, rendering.start));
+                    self.rendering = None;
+                    self.backend.start(transport.clone())?;
+                    self.transport = Some(transport);
+                }
+                Err(mpsc::TryRecvError::Empty) => {}
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.rendering = None;
+                    return Err("The cue could not be mixed down".to_string());
+                }
+            }
+        }
+        match self.backend.take_error() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.backend.stop();
+        self.transport = None;
+        self.rendering = None;
+    }
+
+    /// Whether a cue is being mixed down to be played.
+    pub fn is_rendering(&self) -> bool {
+        self.rendering.is_some()
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.transport.as_ref().is_some_and(|t| t.is_playing())
+    }
+
+    /// Playback position in the previewed cue, if anything is loaded.
+    pub fn position_us(&self) -> Option<u64> {
+        self.transport.as_ref().map(|t| t.position_us())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use common::beat::Beat;
+
+    fn four_beats() -> Cue {
+        let mut cue = Cue::empty();
+        for i in 0..4 {
+            cue.beats.push(Beat {
+                count: i + 1,
+                bar_number: 1,
+                length: 500000,
+            });
+        }
+        cue
+    }
+
+    #[test]
+    fn test_offline_playback() {
+        let cue = four_beats();
+        let buf = render_cue(&cue, &HashMap::new(), 8000);
+        let transport = Arc::new(Transport::new(buf, 8000, 0));
+
+        let mut backend = OfflineBackend::new(8000, 2);
+        backend.start(transport.clone()).unwrap();
+        backend.process(17000);
+        let frames: Vec<&[f32]> = backend.output.chunks(2).collect();
+
+        // Both channels carry the same mono mix
+        assert!(frames.iter().all(|f| f[0] == f[1]));
+        // A click at the start of every beat, silence in between
+        for beat in 0..4 {
+            assert!(frames[beat * 4000 + 1][0].abs() > 0.0);
+            assert_eq!(frames[beat * 4000 + 2000][0], 0.0);
+        }
+        // Stops by itself at the end of the cue
+        assert_eq!(transport.position(), 16001);
+        assert!(!transport.is_playing());
+        assert_eq!(frames[16500][0], 0.0);
+    }
+
+    #[test]
+    fn test_play_after_rendering() {
+        let mut preview = Preview {
+            backend: Box::new(OfflineBackend::new(8000, 1)),
+            ..Default::default()
+        };
+        preview.play(&four_beats(), 0, &ClipManager::default(), 500000);
+        while preview.is_rendering() {
+            preview.poll().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        let transport = preview.transport.as_ref().unwrap();
+        assert_eq!(transport.position(), 4000);
+        assert!(preview.is_playing());
+    }
+}
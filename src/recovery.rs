@@ -0,0 +1,102 @@
+use crate::app::{self, ProjectFile};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+const RECOVERY_FILE: &str = "recovery.bin";
+
+/// Where the recovery snapshot of a show lives: next to show.bin inside clicks.show, or in the
+/// editor's storage directory for shows that haven't been saved yet.
+pub fn recovery_path(project_path: &Path) -> Option<PathBuf> {
+    if !project_path.as_os_str().is_empty() && project_path.is_dir() {
+        Some(project_path.join(RECOVERY_FILE))
+    } else {
+        eframe::storage_dir(app::APP_ID).map(|dir| dir.join(RECOVERY_FILE))
+    }
+}
+
+/// Find a recovery snapshot that is newer than the saved show, if there is one.
+pub fn find_newer(project_file: &ProjectFile) -> Option<PathBuf> {
+    let path = recovery_path(&project_file.path)?;
+    let recovery_time = std::fs::metadata(&path).ok()?.modified().ok()?;
+    let saved_time = std::fs::metadata(project_file.path.join("show.bin"))
+        .and_then(|m| m.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    (recovery_time > saved_time).then_some(path)
+}
+
+/// Replace the show and its time signatures with those of a recovery snapshot.
+pub fn restore(path: &Path, project_file: &mut ProjectFile) -> Result<(), std::io::Error> {
+    let data = std::fs::read(path)?;
+    project_file
+        .restore(&data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+pub fn remove(project_path: &Path) {
+    if let Some(path) = recovery_path(project_path) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Autosave {
+    /// Seconds between snapshots, 0 turns autosave off
+    pub interval: u64,
+    #[serde(skip)]
+    last_check: Option<Instant>,
+    #[serde(skip)]
+    last_written: Vec<u8>,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self {
+            interval: 60,
+            last_check: None,
+            last_written: vec![],
+        }
+    }
+}
+
+impl Autosave {
+    /// Write a recovery snapshot if the interval has passed and there are unsaved changes.
+    pub fn tick(&mut self, project_file: &ProjectFile, dirty: bool) -> Result<(), std::io::Error> {
+        if self.interval == 0 {
+            return Ok(());
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last_check
+            && now.duration_since(last) < Duration::from_secs(self.interval)
+        {
+            return Ok(());
+        }
+        self.last_check = Some(now);
+
+        if !dirty {
+            return Ok(());
+        }
+        let data = project_file
+            .snapshot()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if data == self.last_written {
+            return Ok(());
+        }
+        let Some(path) = recovery_path(&project_file.path) else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        // Write next to it and rename, so a crash mid-write never leaves a broken snapshot
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, &data)?;
+        std::fs::rename(tmp, path)?;
+        self.last_written = data;
+        Ok(())
+    }
+}
@@ -0,0 +1,350 @@
+use crate::cueutils;
+use common::{
+    cue::Cue,
+    event::{EventDescription, JumpModeChange, JumpRequirement, PauseEventBehaviour},
+};
+use std::{collections::HashSet, ops::Range};
+
+/// A jump mode change the operator makes while the cue runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptedChange {
+    /// Microseconds from the start of the cue. The change is made at the start of the first beat
+    /// at or after it.
+    pub time: u64,
+    pub change: JumpModeChange,
+}
+
+/// Plays a cue the way the host does, without making a sound.
+///
+/// Events on a beat take effect when the beat has been played: a jump event on beat 7 plays beat
+/// 7 and then goes on from its destination. Pauses are resumed straight away, so the times of the
+/// run leave out however long the operator would wait.
+#[derive(Debug, Clone)]
+pub struct Simulation {
+    /// Jump mode at the start of the cue
+    pub jump_mode: bool,
+    pub script: Vec<ScriptedChange>,
+    /// Stop after this many beats, since a vamp nobody leaves never ends
+    pub max_beats: usize,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self {
+            jump_mode: true,
+            script: vec![],
+            max_beats: 10000,
+        }
+    }
+}
+
+/// One beat played in a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub beat_idx: usize,
+    /// Microseconds from the start of the cue
+    pub time: u64,
+    /// Jump mode while the beat plays
+    pub jump_mode: bool,
+}
+
+/// A pause the run went through, after the step it is on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pause {
+    pub step_idx: usize,
+    pub behaviour: PauseEventBehaviour,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ending {
+    /// Played past the last beat
+    EndOfCue,
+    /// A pause event moved on to the next cue
+    NextCue,
+    /// Came back to a beat the way it had before, with nothing left in the script to change
+    /// what happens next, so it would go round forever
+    Loop,
+    /// Ran into `max_beats`
+    BeatLimit,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Run {
+    pub steps: Vec<Step>,
+    pub pauses: Vec<Pause>,
+    pub ending: Ending,
+    /// Microseconds from the start of the cue to the end of the last step
+    pub length: u64,
+}
+
+impl Run {
+    /// Beat numbers in the order they were played
+    pub fn beats(&self) -> Vec<usize> {
+        self.steps.iter().map(|s| s.beat_idx).collect()
+    }
+
+    /// Ranges of steps that play through without a jump or a pause in between.
+    pub fn passes(&self) -> Vec<Range<usize>> {
+        let mut passes = vec![];
+        let mut start = 0;
+        for i in 1..=self.steps.len() {
+            let broken = i == self.steps.len()
+                || self.steps[i].beat_idx != self.steps[i - 1].beat_idx + 1
+                || self.pauses.iter().any(|p| p.step_idx == i - 1);
+            if broken {
+                passes.push(start..i);
+                start = i;
+            }
+        }
+        passes
+    }
+
+    /// Time from the start of the first step of a range to the end of its last.
+    pub fn duration(&self, steps: Range<usize>) -> u64 {
+        let start = self.steps.get(steps.start).map_or(self.length, |s| s.time);
+        let end = self.steps.get(steps.end).map_or(self.length, |s| s.time);
+        end.saturating_sub(start)
+    }
+}
+
+fn change_mode(jump_mode: &mut bool, change: &JumpModeChange) {
+    match change {
+        JumpModeChange::None => {}
+        JumpModeChange::SetOn => *jump_mode = true,
+        JumpModeChange::SetOff => *jump_mode = false,
+        JumpModeChange::Toggle => *jump_mode = !*jump_mode,
+    }
+}
+
+fn requirement_met(requirement: &JumpRequirement, jump_mode: bool) -> bool {
+    match requirement {
+        JumpRequirement::None => true,
+        JumpRequirement::JumpModeOn => jump_mode,
+        JumpRequirement::JumpModeOff => !jump_mode,
+    }
+}
+
+impl Simulation {
+    pub fn run(&self, cue: &Cue) -> Run {
+        let num_beats = cue.beats.len();
+        let events_at = cueutils::events_by_beat(cue);
+        let mut script = self.script.clone();
+        script.sort_by_key(|c| c.time);
+        let mut script = script.into_iter().peekable();
+
+        let mut jump_mode = self.jump_mode;
+        let mut steps = vec![];
+        let mut pauses = vec![];
+        let mut time = 0;
+        let mut idx = 0;
+        let mut jumped_to: HashSet<(usize, bool)> = HashSet::new();
+        let ending = loop {
+            if idx >= num_beats {
+                break Ending::EndOfCue;
+            }
+            if steps.len() >= self.max_beats {
+                break Ending::BeatLimit;
+            }
+            while let Some(scripted) = script.next_if(|c| c.time <= time) {
+                change_mode(&mut jump_mode, &scripted.change);
+            }
+            steps.push(Step {
+                beat_idx: idx,
+                time,
+                jump_mode,
+            });
+            time += cue.beats[idx].length as u64;
+
+            // Only the first jump that can be taken on a beat is, the others count as passed
+            let mut next = Some(idx + 1);
+            let mut jumped = false;
+            for event in &events_at[idx] {
+                match &event.event {
+                    Some(EventDescription::JumpEvent {
+                        destination,
+                        requirement,
+                        when_jumped,
+                        when_passed,
+                    }) => {
+                        if !jumped && requirement_met(requirement, jump_mode) {
+                            jumped = true;
+                            next = Some(*destination as usize);
+                            change_mode(&mut jump_mode, when_jumped);
+                        } else {
+                            change_mode(&mut jump_mode, when_passed);
+                        }
+                    }
+                    Some(EventDescription::PauseEvent { behaviour }) => {
+                        pauses.push(Pause {
+                            step_idx: steps.len() - 1,
+                            behaviour: behaviour.clone(),
+                        });
+                        match behaviour {
+                            PauseEventBehaviour::Hold => {}
+                            PauseEventBehaviour::RestartBeat => next = Some(idx),
+                            PauseEventBehaviour::RestartCue => next = Some(0),
+                            PauseEventBehaviour::NextCue => next = None,
+                            PauseEventBehaviour::Jump { destination } => {
+                                next = Some(*destination as usize)
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            match next {
+                Some(next) if next == idx + 1 => idx = next,
+                Some(next) => {
+                    if script.peek().is_none() && !jumped_to.insert((next, jump_mode)) {
+                        break Ending::Loop;
+                    }
+                    idx = next;
+                }
+                None => break Ending::NextCue,
+            }
+        };
+
+        Run {
+            steps,
+            pauses,
+            ending,
+            length: time,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cueutils::testing::cue;
+    use common::event::Event;
+
+    fn jump(
+        destination: usize,
+        requirement: JumpRequirement,
+        when_jumped: JumpModeChange,
+        when_passed: JumpModeChange,
+    ) -> EventDescription {
+        EventDescription::JumpEvent {
+            destination: destination as _,
+            requirement,
+            when_jumped,
+            when_passed,
+        }
+    }
+
+    fn repeat(destination: usize) -> EventDescription {
+        jump(
+            destination,
+            JumpRequirement::JumpModeOn,
+            JumpModeChange::SetOff,
+            JumpModeChange::SetOn,
+        )
+    }
+
+    #[test]
+    fn test_straight_through() {
+        let run = Simulation::default().run(&cue(4));
+        assert_eq!(run.beats(), [0, 1, 2, 3]);
+        assert_eq!(
+            run.steps.iter().map(|s| s.time).collect::<Vec<u64>>(),
+            [0, 500000, 1000000, 1500000]
+        );
+        assert_eq!(run.length, 2000000);
+        assert_eq!(run.ending, Ending::EndOfCue);
+    }
+
+    #[test]
+    fn test_repeat() {
+        let mut cue = cue(6);
+        cue.events.push(Event::new(3, repeat(0)));
+        let run = Simulation::default().run(&cue);
+        assert_eq!(run.beats(), [0, 1, 2, 3, 0, 1, 2, 3, 4, 5]);
+        // Passing the repeat the second time sets jump mode back on for the next one
+        assert!(run.steps.last().unwrap().jump_mode);
+    }
+
+    #[test]
+    fn test_volta() {
+        // Beats 2-3 are the first ending and 4-5 the second
+        let mut cue = cue(8);
+        cue.events.push(Event::new(
+            1,
+            jump(
+                4,
+                JumpRequirement::JumpModeOff,
+                JumpModeChange::SetOn,
+                JumpModeChange::None,
+            ),
+        ));
+        cue.events.push(Event::new(3, repeat(0)));
+        let run = Simulation::default().run(&cue);
+        assert_eq!(run.beats(), [0, 1, 2, 3, 0, 1, 4, 5, 6, 7]);
+        assert_eq!(run.length, 5000000);
+    }
+
+    #[test]
+    fn test_vamp() {
+        let mut cue = cue(4);
+        cue.events.push(Event::new(
+            2,
+            jump(
+                1,
+                JumpRequirement::JumpModeOn,
+                JumpModeChange::None,
+                JumpModeChange::SetOff,
+            ),
+        ));
+        let mut sim = Simulation::default();
+        let run = sim.run(&cue);
+        assert_eq!(run.beats(), [0, 1, 2, 1, 2]);
+        assert_eq!(run.ending, Ending::Loop);
+
+        // Jump mode goes off late in the third pass, and the host only sees it on the next beat
+        sim.script.push(ScriptedChange {
+            time: 2900000,
+            change: JumpModeChange::Toggle,
+        });
+        let run = sim.run(&cue);
+        assert_eq!(run.beats(), [0, 1, 2, 1, 2, 1, 2, 3]);
+        assert_eq!(run.ending, Ending::EndOfCue);
+    }
+
+    #[test]
+    fn test_pauses() {
+        let mut cue = cue(6);
+        cue.events.push(Event::new(
+            1,
+            EventDescription::PauseEvent {
+                behaviour: PauseEventBehaviour::Jump { destination: 3 },
+            },
+        ));
+        cue.events.push(Event::new(
+            4,
+            EventDescription::PauseEvent {
+                behaviour: PauseEventBehaviour::NextCue,
+            },
+        ));
+        let run = Simulation::default().run(&cue);
+        assert_eq!(run.beats(), [0, 1, 3, 4]);
+        assert_eq!(
+            run.pauses
+                .iter()
+                .map(|p| p.step_idx)
+                .collect::<Vec<usize>>(),
+            [1, 3]
+        );
+        assert_eq!(run.ending, Ending::NextCue);
+
+        let mut cue = self::cue(3);
+        cue.events.push(Event::new(
+            1,
+            EventDescription::PauseEvent {
+                behaviour: PauseEventBehaviour::Hold,
+            },
+        ));
+        let run = Simulation::default().run(&cue);
+        assert_eq!(run.beats(), [0, 1, 2]);
+        assert_eq!(run.pauses.len(), 1);
+    }
+}
@@ -0,0 +1,196 @@
+use common::{
+    beat::Beat,
+    cue::{Cue, CueMetadata},
+    event::{Event, EventDescription},
+    mem::str::StaticString,
+};
+use serde::{Deserialize, Serialize};
+
+/// Samples per frame of the onset envelope.
+const HOP: usize = 512;
+const MIN_BPM: f64 = 60.0;
+const MAX_BPM: f64 = 200.0;
+/// Tempo the octave weighting leans towards, so 60 and 240 BPM readings of a 120 BPM track lose
+const PREFERRED_BPM: f64 = 120.0;
+
+/// Estimated tempo and beat grid of an audio clip.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Detection {
+    pub bpm: f64,
+    /// Sample, counted per channel, of the first beat
+    pub offset: usize,
+    /// How strongly the clip pulses at the tempo, from 0 to 1
+    pub confidence: f32,
+}
+
+/// Rise in loudness from frame to frame, which peaks wherever a note starts.
+fn onset_envelope(samples: &[f32], channels: usize) -> Vec<f32> {
+    let channels = channels.max(1);
+    let log_energy: Vec<f32> = samples
+        .chunks(HOP * channels)
+        .map(|frame| {
+            let energy: f32 = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+            (energy + 1e-9).ln()
+        })
+        .collect();
+    let flux: Vec<f32> = log_energy
+        .windows(2)
+        .map(|w| (w[1] - w[0]).max(0.0))
+        .collect();
+
+    // Take away the local average, so only the sharpest rises are left
+    let window = 16;
+    (0..flux.len())
+        .map(|i| {
+            let start = i.saturating_sub(window);
+            let end = (i + window).min(flux.len());
+            let mean = flux[start..end].iter().sum::<f32>() / (end - start) as f32;
+            (flux[i] - mean).max(0.0)
+        })
+        .collect()
+}
+
+fn autocorrelation(env: &[f32], lag: usize) -> f32 {
+    if lag >= env.len() {
+        return 0.0;
+    }
+    env.iter().zip(&env[lag..]).map(|(a, b)| a * b).sum::<f32>() / (env.len() - lag) as f32
+}
+
+/// Estimate the tempo of interleaved audio from its onsets.
+///
+/// Returns None for clips that are silent or too short to hold a few beats.
+pub fn detect(samples: &[f32], channels: usize, sample_rate: u32) -> Option<Detection> {
+    let env = onset_envelope(samples, channels);
+    let frame_rate = sample_rate as f64 / HOP as f64;
+    let lag_of = |bpm: f64| (60.0 * frame_rate / bpm).round() as usize;
+    let (min_lag, max_lag) = (lag_of(MAX_BPM).max(1), lag_of(MIN_BPM));
+    if env.len() < max_lag * 4 {
+        return None;
+    }
+    let energy = autocorrelation(&env, 0);
+    if energy <= 0.0 {
+        return None;
+    }
+
+    let weight = |lag: usize| {
+        let bpm = 60.0 * frame_rate / lag as f64;
+        (-0.5 * ((bpm / PREFERRED_BPM).log2()).powi(2)).exp() as f32
+    };
+    let (best_lag, _) = (min_lag..=max_lag)
+        .map(|lag| (lag, autocorrelation(&env, lag) * weight(lag)))
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    // Fit a parabola through the neighbours for a period between whole frames
+    let (prev, here, next) = (
+        autocorrelation(&env, best_lag - 1),
+        autocorrelation(&env, best_lag),
+        autocorrelation(&env, best_lag + 1),
+    );
+    let denom = prev - 2.0 * here + next;
+    let shift = if denom.abs() > f32::EPSILON {
+        (0.5 * (prev - next) / denom).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    };
+    let period = best_lag as f64 + shift as f64;
+
+    // Phase: where a grid at that period hits the most onsets
+    let (offset_frame, _) = (0..best_lag)
+        .map(|offset| {
+            let score: f32 = (0..)
+                .map(|k| (offset as f64 + k as f64 * period).round() as usize)
+                .take_while(|i| *i < env.len())
+                .map(|i| env[i])
+                .sum();
+            (offset, score)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+
+    Some(Detection {
+        bpm: 60.0 * frame_rate / period,
+        // The envelope is one frame behind the audio, since it is made of differences
+        offset: (offset_frame + 1) * HOP,
+        confidence: (here / energy).clamp(0.0, 1.0),
+    })
+}
+
+/// Build a cue of whole bars at the detected tempo, playing the clip from its first beat.
+/// `clip_frames` is the length of the clip in samples per channel.
+pub fn cue_skeleton(
+    detection: &Detection,
+    name: &str,
+    clip_frames: usize,
+    sample_rate: u32,
+    channel_idx: usize,
+    clip_idx: usize,
+    beats_per_bar: usize,
+) -> Cue {
+    // Tempo events only hold whole BPM, so the beats are laid out at the same rounded tempo
+    let bpm = detection.bpm.round().max(1.0);
+    let samples_per_beat = sample_rate as f64 * 60.0 / bpm;
+    let beats_per_bar = beats_per_bar.max(1);
+    let num_beats =
+        (clip_frames.saturating_sub(detection.offset) as f64 / samples_per_beat).ceil() as usize;
+    let num_beats = num_beats.div_ceil(beats_per_bar).max(1) * beats_per_bar;
+
+    let mut cue = Cue::empty();
+    cue.beats.clear();
+    cue.metadata = CueMetadata {
+        name: StaticString::new(name),
+        human_ident: StaticString::new("000"),
+    };
+    for i in 0..num_beats {
+        cue.beats.push(Beat {
+            count: (i % beats_per_bar) as u8 + 1,
+            bar_number: (i / beats_per_bar + 1) as _,
+            length: (60_000_000.0 / bpm).round() as u32,
+        });
+    }
+    cue.events.push(Event::new(
+        0,
+        EventDescription::TempoChangeEvent { tempo: bpm as _ },
+    ));
+    cue.events.push(Event::new(
+        0,
+        EventDescription::PlaybackEvent {
+            // Playback runs at 48 kHz whatever the rate of the file
+            sample: (detection.offset as u64 * 48000 / sample_rate.max(1) as u64) as i32,
+            channel_idx: channel_idx as _,
+            clip_idx: clip_idx as _,
+        },
+    ));
+    cue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A mono click track with short 1 kHz blips on every beat, the first at `first` samples.
+    fn click_track(bpm: f64, sample_rate: u32, seconds: usize, first: usize) -> Vec<f32> {
+        let mut samples = vec![0.0; sample_rate as usize * seconds];
+        let period = sample_rate as f64 * 60.0 / bpm;
+        let blip = sample_rate as usize / 50;
+        for start in (0..)
+            .map(|k| (first as f64 + k as f64 * period).round() as usize)
+            .take_while(|start| *start < samples.len())
+        {
+            for (i, sample) in samples[start..].iter_mut().take(blip).enumerate() {
+                let t = i as f32 / sample_rate as f32;
+                *sample = (std::f32::consts::TAU * 1000.0 * t).sin() * (-t * 200.0).exp();
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn test_detect_click_track() {
+        let samples = click_track(128.0, 44100, 12, 4410);
+        let detection = detect(&samples, 1, 44100).unwrap();
+        assert!((detection.bpm - 128.0).abs() < 1.0, "{detection:?}");
+        assert!(detection.offset.abs_diff(4410) < 2 * HOP, "{detection:?}");
+
+        assert_eq!(detect(&vec![0.0; 44100 * 12], 1, 44100), None);
+    }
+}
@@ -0,0 +1,335 @@
+use crate::{
+    cueutils,
+    meter::{Meter, Meters},
+};
+use common::{cue::Cue, event::EventDescription};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// How the tempo moves from start to end of a gradual tempo change.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ramp {
+    /// The same number of BPM every beat
+    #[default]
+    Linear,
+    /// The same ratio every beat, which sounds even over wide tempo ranges
+    Exponential,
+}
+
+/// What the BPM of a tempo change counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TempoUnit {
+    /// Clicks per minute, whatever the note value of the beats
+    #[default]
+    Click,
+    Quarter,
+    DottedQuarter,
+}
+
+impl TempoUnit {
+    /// Length of the unit in quarter notes, or None if it follows the beats
+    fn quarters(&self) -> Option<f64> {
+        match self {
+            Self::Click => None,
+            Self::Quarter => Some(1.0),
+            Self::DottedQuarter => Some(1.5),
+        }
+    }
+}
+
+/// Tempo changes that fight over the same beats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub beat_idx: usize,
+    pub message: String,
+}
+
+/// Works out the length of every beat of a cue from its tempo changes.
+///
+/// Beats before the first tempo change keep the length they have. A gradual tempo change starts
+/// at its start tempo on its own beat and reaches its end tempo `length` beats later, which is
+/// where the tempo stays until the next change.
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    pub ramp: Ramp,
+    pub unit: TempoUnit,
+    /// Note value of each beat in quarter notes, 1 unless a meter says otherwise
+    pub beat_notes: Vec<f64>,
+}
+
+impl TempoMap {
+    pub fn new(cue: &Cue) -> Self {
+        Self {
+            ramp: Ramp::default(),
+            unit: TempoUnit::default(),
+            beat_notes: vec![1.0; cue.beats.len()],
+        }
+    }
+
+    /// Give the beats in `range` the note values of `meter`, bar by bar. The range should start
+    /// on a downbeat.
+    pub fn set_meter(&mut self, range: Range<usize>, meter: Meter) {
+        let note = 4.0 / meter.denominator.max(1) as f64;
+        let groups = meter.groups();
+        let end = range.end.min(self.beat_notes.len());
+        for (i, idx) in (range.start..end).enumerate() {
+            self.beat_notes[idx] = groups[i % groups.len()] as f64 * note;
+        }
+    }
+
+    /// Give every bar of the cue the note values of the time signature it is in.
+    pub fn set_meters(&mut self, cue: &Cue, meters: &Meters) {
+        for (range, meter) in meters.bars(cue) {
+            self.set_meter(range, meter);
+        }
+    }
+
+    /// Tempo in effect at the start of every beat, None before the first tempo change.
+    pub fn tempos(&self, cue: &Cue) -> (Vec<Option<f64>>, Vec<Conflict>) {
+        let num_beats = cue.beats.len();
+        let mut tempos: Vec<Option<f64>> = vec![None; num_beats];
+        let mut conflicts = vec![];
+
+        let mut events = cueutils::events(cue);
+        events.sort_by_key(|e| e.location);
+
+        // Later changes override earlier ones from their own beat on
+        let mut ramp: Option<(usize, usize)> = None;
+        let mut set_on: Vec<Option<f64>> = vec![None; num_beats];
+        for event in &events {
+            let loc = event.location as usize;
+            if loc >= num_beats {
+                continue;
+            }
+            let (start, end, length) = match event.event {
+                Some(EventDescription::TempoChangeEvent { tempo }) => {
+                    (tempo as f64, tempo as f64, 0)
+                }
+                Some(EventDescription::GradualTempoChangeEvent {
+                    start_tempo,
+                    end_tempo,
+                    length,
+                }) => (start_tempo as f64, end_tempo as f64, length as usize),
+                _ => continue,
+            };
+            if start <= 0.0 || end <= 0.0 {
+                conflicts.push(Conflict {
+                    beat_idx: loc,
+                    message: "Tempo change to 0 BPM is ignored".to_string(),
+                });
+                continue;
+            }
+
+            if let Some(other) = set_on[loc]
+                && other != start
+            {
+                conflicts.push(Conflict {
+                    beat_idx: loc,
+                    message: format!("Tempo changes to both {other} and {start} BPM on one beat"),
+                });
+            }
+            if let Some((ramp_loc, ramp_len)) = ramp
+                && loc > ramp_loc
+                && loc < ramp_loc + ramp_len
+            {
+                conflicts.push(Conflict {
+                    beat_idx: loc,
+                    message: format!(
+                        "{} cuts off the gradual tempo change at beat {ramp_loc}",
+                        if length > 0 {
+                            "Gradual tempo change"
+                        } else {
+                            "Tempo change"
+                        }
+                    ),
+                });
+            }
+
+            // Everything from here on follows this change
+            for (step, tempo) in tempos[loc..].iter_mut().enumerate() {
+                *tempo = Some(if step >= length {
+                    end
+                } else {
+                    self.ramp_tempo(start, end, step as f64 / length as f64)
+                });
+            }
+            set_on[loc] = Some(start);
+            ramp = (length > 0).then_some((loc, length));
+        }
+
+        (tempos, conflicts)
+    }
+
+    fn ramp_tempo(&self, start: f64, end: f64, progress: f64) -> f64 {
+        match self.ramp {
+            Ramp::Linear => start + (end - start) * progress,
+            Ramp::Exponential => start * (end / start).powf(progress),
+        }
+    }
+
+    /// Length of every beat in microseconds.
+    pub fn lengths(&self, cue: &Cue) -> (Vec<u32>, Vec<Conflict>) {
+        let (tempos, conflicts) = self.tempos(cue);
+        let lengths = cue
+            .beats
+            .iter()
+            .zip(tempos)
+            .enumerate()
+            .map(|(idx, (beat, tempo))| match tempo {
+                None => beat.length,
+                Some(tempo) => {
+                    let notes = self.beat_notes.get(idx).copied().unwrap_or(1.0);
+                    let units = self.unit.quarters().map_or(1.0, |unit| notes / unit);
+                    (60_000_000.0 / tempo * units).round() as u32
+                }
+            })
+            .collect();
+        (lengths, conflicts)
+    }
+
+    /// Set the length of every beat of the cue, returning any conflicts on the way.
+    pub fn apply(&self, cue: &mut Cue) -> Vec<Conflict> {
+        let (lengths, conflicts) = self.lengths(cue);
+        for (beat, length) in cue.beats.iter_mut().zip(lengths) {
+            beat.length = length;
+        }
+        conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cueutils::testing::cue;
+    use common::event::Event;
+
+    #[test]
+    fn test_steady_tempo() {
+        let mut cue = cue(8);
+        cue.events.push(Event::new(
+            2,
+            EventDescription::TempoChangeEvent { tempo: 100 },
+        ));
+        cue.events.push(Event::new(
+            5,
+            EventDescription::TempoChangeEvent { tempo: 150 },
+        ));
+        let (lengths, conflicts) = TempoMap::new(&cue).lengths(&cue);
+        assert!(conflicts.is_empty());
+        assert_eq!(
+            lengths,
+            [
+                500000, 500000, 600000, 600000, 600000, 400000, 400000, 400000
+            ]
+        );
+    }
+
+    #[test]
+    fn test_linear_ramp() {
+        let mut cue = cue(6);
+        cue.events.push(Event::new(
+            1,
+            EventDescription::GradualTempoChangeEvent {
+                start_tempo: 120,
+                end_tempo: 60,
+                length: 2,
+            },
+        ));
+        let (lengths, conflicts) = TempoMap::new(&cue).lengths(&cue);
+        assert!(conflicts.is_empty());
+        // 120, 90, then 60 BPM from the end of the ramp on
+        assert_eq!(lengths, [500000, 500000, 666667, 1000000, 1000000, 1000000]);
+    }
+
+    #[test]
+    fn test_exponential_ramp() {
+        let mut cue = cue(4);
+        cue.events.push(Event::new(
+            0,
+            EventDescription::GradualTempoChangeEvent {
+                start_tempo: 60,
+                end_tempo: 240,
+                length: 2,
+            },
+        ));
+        let mut map = TempoMap::new(&cue);
+        map.ramp = Ramp::Exponential;
+        let (lengths, _) = map.lengths(&cue);
+        // 60, 120, 240 BPM, halving the beat every step
+        assert_eq!(lengths, [1000000, 500000, 250000, 250000]);
+    }
+
+    #[test]
+    fn test_units() {
+        let mut cue = cue(6);
+        cue.events.push(Event::new(
+            0,
+            EventDescription::TempoChangeEvent { tempo: 80 },
+        ));
+        let mut map = TempoMap::new(&cue);
+        map.unit = TempoUnit::DottedQuarter;
+        map.set_meter(0..6, Meter::new(6, 8));
+        let (lengths, _) = map.lengths(&cue);
+        // Dotted quarter at 80 BPM is 750 ms, and 6/8 clicks once per dotted quarter
+        assert_eq!(&lengths[..2], [750000, 750000]);
+
+        map.set_meter(
+            0..6,
+            Meter {
+                subdivide: true,
+                ..Meter::new(6, 8)
+            },
+        );
+        let (lengths, _) = map.lengths(&cue);
+        assert_eq!(lengths, [250000; 6]);
+
+        map.unit = TempoUnit::Quarter;
+        map.set_meter(0..6, Meter::new(7, 8));
+        let (lengths, _) = map.lengths(&cue);
+        // Quarter at 80 BPM, 7/8 in 2+2+3 eighths
+        assert_eq!(&lengths[..3], [750000, 750000, 1125000]);
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let mut cue = cue(8);
+        cue.events.push(Event::new(
+            0,
+            EventDescription::GradualTempoChangeEvent {
+                start_tempo: 100,
+                end_tempo: 140,
+                length: 4,
+            },
+        ));
+        cue.events.push(Event::new(
+            2,
+            EventDescription::TempoChangeEvent { tempo: 90 },
+        ));
+        cue.events.push(Event::new(
+            4,
+            EventDescription::GradualTempoChangeEvent {
+                start_tempo: 90,
+                end_tempo: 60,
+                length: 3,
+            },
+        ));
+        cue.events.push(Event::new(
+            6,
+            EventDescription::GradualTempoChangeEvent {
+                start_tempo: 70,
+                end_tempo: 60,
+                length: 2,
+            },
+        ));
+        let (lengths, conflicts) = TempoMap::new(&cue).lengths(&cue);
+        assert_eq!(
+            conflicts.iter().map(|c| c.beat_idx).collect::<Vec<usize>>(),
+            [2, 6]
+        );
+        // The tempo change wins over the ramp it cuts off, and so does the later ramp
+        assert_eq!(lengths[2], 666667);
+        assert_eq!(lengths[3], 666667);
+        assert_eq!(lengths[5], 750000);
+        assert_eq!(lengths[6], 857143);
+    }
+}
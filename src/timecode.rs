@@ -0,0 +1,497 @@
+use crate::{
+    cueutils,
+    simulate::{Run, Simulation},
+};
+use common::{
+    cue::{Cue, Show},
+    event::{Event, EventDescription},
+    mem::smpte::TimecodeInstant,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt::Display, rc::Rc};
+
+/// Frame rates timecode can run at.
+///
+/// The show stores the frame rate of a timecode event as a whole number of frames per second.
+/// This is the editor's reading of that number: 24, 25 and 30 are what they say, and 29 stands
+/// for 29.97 drop frame, which is what a whole number of 29 frames per second would mean to a
+/// timecode reader. Any other number is a frame rate the editor doesn't know, and is left alone
+/// rather than being guessed at.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameRate {
+    Fps24,
+    #[default]
+    Fps25,
+    /// 29.97 frames per second, skipping frame numbers 0 and 1 every minute except every tenth
+    /// so the timecode keeps up with the clock
+    Fps2997Drop,
+    Fps30,
+}
+
+impl Display for FrameRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fps24 => write!(f, "24 fps"),
+            Self::Fps25 => write!(f, "25 fps"),
+            Self::Fps2997Drop => write!(f, "29.97 fps drop frame"),
+            Self::Fps30 => write!(f, "30 fps"),
+        }
+    }
+}
+
+impl FrameRate {
+    pub const ALL: [FrameRate; 4] = [Self::Fps24, Self::Fps25, Self::Fps2997Drop, Self::Fps30];
+
+    /// Frame numbers in a second of timecode
+    pub fn nominal(&self) -> u32 {
+        match self {
+            Self::Fps24 => 24,
+            Self::Fps25 => 25,
+            Self::Fps2997Drop | Self::Fps30 => 30,
+        }
+    }
+
+    /// Frames in a second of real time
+    pub fn fps(&self) -> f64 {
+        match self {
+            Self::Fps2997Drop => 30000.0 / 1001.0,
+            _ => self.nominal() as f64,
+        }
+    }
+
+    pub fn drop_frame(&self) -> bool {
+        *self == Self::Fps2997Drop
+    }
+
+    /// The number the show stores for this frame rate
+    pub fn stored(&self) -> u8 {
+        match self {
+            Self::Fps2997Drop => 29,
+            _ => self.nominal() as u8,
+        }
+    }
+
+    pub fn from_stored(fps: u32) -> Option<Self> {
+        match fps {
+            24 => Some(Self::Fps24),
+            25 => Some(Self::Fps25),
+            29 => Some(Self::Fps2997Drop),
+            30 => Some(Self::Fps30),
+            _ => None,
+        }
+    }
+}
+
+/// A position in timecode, as a frame count from 00:00:00:00.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    pub frame: i64,
+    pub rate: FrameRate,
+}
+
+impl Timecode {
+    pub fn from_hmsf(h: i64, m: i64, s: i64, f: i64, rate: FrameRate) -> Self {
+        let nominal = rate.nominal() as i64;
+        let mut frame = ((h * 60 + m) * 60 + s) * nominal + f;
+        if rate.drop_frame() {
+            let minutes = h * 60 + m;
+            frame -= 2 * (minutes - minutes / 10);
+        }
+        Self { frame, rate }
+    }
+
+    /// The timecode of an instant, if its frame rate is one the editor knows.
+    pub fn from_instant(time: &TimecodeInstant) -> Option<Self> {
+        Some(Self::from_hmsf(
+            time.h as i64,
+            time.m as i64,
+            time.s as i64,
+            time.f as i64,
+            FrameRate::from_stored(time.frame_rate as u32)?,
+        ))
+    }
+
+    pub fn to_instant(&self) -> TimecodeInstant {
+        let (h, m, s, f) = self.hmsf();
+        let mut time = TimecodeInstant::new(self.rate.stored() as _);
+        time.h = h as _;
+        time.m = m as _;
+        time.s = s as _;
+        time.f = f as _;
+        time
+    }
+
+    /// Hours, minutes, seconds and frames, wrapping around after 24 hours
+    pub fn hmsf(&self) -> (i64, i64, i64, i64) {
+        let nominal = self.rate.nominal() as i64;
+        let day = (24.0 * 3600.0 * self.rate.fps()).round() as i64;
+        let mut frame = self.frame.rem_euclid(day);
+        if self.rate.drop_frame() {
+            // 17982 frames in every ten minutes, 1798 in every minute but the first of them
+            let tens = frame / 17982;
+            let rest = frame % 17982;
+            frame += 18 * tens;
+            if rest >= 2 {
+                frame += 2 * ((rest - 2) / 1798);
+            }
+        }
+        (
+            frame / (nominal * 3600),
+            frame / (nominal * 60) % 60,
+            frame / nominal % 60,
+            frame % nominal,
+        )
+    }
+
+    /// Timecode some microseconds later.
+    pub fn after(&self, us: u64) -> Self {
+        Self {
+            frame: self.frame + (us as f64 * self.rate.fps() / 1_000_000.0).floor() as i64,
+            rate: self.rate,
+        }
+    }
+
+    /// Microseconds from 00:00:00:00
+    pub fn to_us(&self) -> u64 {
+        (self.frame.max(0) as f64 * 1_000_000.0 / self.rate.fps()).round() as u64
+    }
+}
+
+impl Display for Timecode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (h, m, s, fr) = self.hmsf();
+        let sep = if self.rate.drop_frame() { ';' } else { ':' };
+        write!(f, "{h:02}:{m:02}:{s:02}{sep}{fr:02}")
+    }
+}
+
+/// Clock time and running timecode of every beat of a cue, the first time it is reached when the
+/// cue plays. Beats that are never played have neither.
+#[derive(Debug, Default, Clone)]
+pub struct BeatTimes {
+    /// Microseconds from the start of the cue
+    pub times: Vec<Option<u64>>,
+    pub timecodes: Vec<Option<Timecode>>,
+}
+
+impl BeatTimes {
+    pub fn new(cue: &Cue) -> Self {
+        let run = Simulation::default().run(cue);
+        Self {
+            times: first_reached(cue, &run),
+            timecodes: running_timecodes(cue, &run),
+        }
+    }
+}
+
+/// Beat times of the cues of a show, kept until the show changes.
+#[derive(Debug, Default)]
+pub struct BeatTimesCache {
+    /// History revision of the show the cached times belong to
+    revision: u64,
+    cues: HashMap<usize, Rc<BeatTimes>>,
+}
+
+impl BeatTimesCache {
+    /// Beat times of cue `cue_idx`, which is `cue` as of history revision `revision`.
+    pub fn get(&mut self, revision: u64, cue_idx: usize, cue: &Cue) -> Rc<BeatTimes> {
+        if revision != self.revision {
+            self.cues.clear();
+            self.revision = revision;
+        }
+        self.cues
+            .entry(cue_idx)
+            .or_insert_with(|| Rc::new(BeatTimes::new(cue)))
+            .clone()
+    }
+}
+
+fn first_reached(cue: &Cue, run: &Run) -> Vec<Option<u64>> {
+    let mut times = vec![None; cue.beats.len()];
+    for step in &run.steps {
+        times[step.beat_idx].get_or_insert(step.time);
+    }
+    times
+}
+
+/// Timecode the first time every beat is reached, wherever timecode is running then. Timecode
+/// events start over every time their beat is played, the same as in the rendered LTC.
+fn running_timecodes(cue: &Cue, run: &Run) -> Vec<Option<Timecode>> {
+    let events_at = cueutils::events_by_beat(cue);
+    let mut timecodes = vec![None; cue.beats.len()];
+    let mut reached = vec![false; cue.beats.len()];
+    let mut running: Option<(Timecode, u64)> = None;
+    for step in &run.steps {
+        for event in &events_at[step.beat_idx] {
+            match event.event {
+                Some(EventDescription::TimecodeEvent { time }) => {
+                    running = Timecode::from_instant(&time).map(|tc| (tc, step.time));
+                }
+                Some(EventDescription::TimecodeStopEvent) => running = None,
+                _ => {}
+            }
+        }
+        if !std::mem::replace(&mut reached[step.beat_idx], true) {
+            timecodes[step.beat_idx] =
+                running.map(|(start, start_time)| start.after(step.time - start_time));
+        }
+    }
+    timecodes
+}
+
+/// Where a timecode plan puts its timecode events.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChasePoints {
+    #[default]
+    CueStarts,
+    /// At every rehearsal mark, and at the start of cues that have none
+    RehearsalMarks,
+}
+
+/// How the timecode of one cue follows on from the one before.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChasePolicy {
+    /// Each cue carries on from where the timecode of the cue before ended
+    #[default]
+    Continuous,
+    /// Every cue starts from the same timecode
+    RestartEachCue,
+    /// Every cue starts an hour after the one before
+    HourPerCue,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChasePlan {
+    pub start: Timecode,
+    pub points: ChasePoints,
+    pub policy: ChasePolicy,
+}
+
+/// Replace the timecode events of every cue with ones at the points of the plan. Times inside a
+/// cue are those the beats are first reached at when it plays, and pauses take no time. Returns
+/// the number of events added.
+pub fn plan_chase(show: &mut Show, plan: &ChasePlan) -> usize {
+    let hour = Timecode::from_hmsf(1, 0, 0, 0, plan.start.rate).frame;
+    let mut next_start = plan.start;
+    let mut added = 0;
+    for (cue_idx, cue) in show.cues.iter_mut().enumerate() {
+        let start = match plan.policy {
+            ChasePolicy::Continuous => next_start,
+            ChasePolicy::RestartEachCue => plan.start,
+            ChasePolicy::HourPerCue => Timecode {
+                frame: plan.start.frame + hour * cue_idx as i64,
+                ..plan.start
+            },
+        };
+        let run = Simulation::default().run(cue);
+        next_start = start.after(run.length);
+        if cue.beats.is_empty() {
+            continue;
+        }
+
+        let times = first_reached(cue, &run);
+        let mut events = cueutils::events(cue);
+        let mut points: Vec<usize> = match plan.points {
+            ChasePoints::CueStarts => vec![0],
+            ChasePoints::RehearsalMarks => events
+                .iter()
+                .filter(|e| matches!(e.event, Some(EventDescription::RehearsalMarkEvent { .. })))
+                .map(|e| e.location as usize)
+                .filter(|idx| *idx < cue.beats.len())
+                .collect(),
+        };
+        // Timecode can't be picked up on beats that are never played
+        points.retain(|idx| times[*idx].is_some());
+        if points.is_empty() {
+            points.push(0);
+        }
+        points.sort();
+        points.dedup();
+
+        events.retain(|e| !matches!(e.event, Some(EventDescription::TimecodeEvent { .. })));
+        for point in points {
+            events.push(Event::new(
+                point as u16,
+                EventDescription::TimecodeEvent {
+                    time: start.after(times[point].unwrap_or(0)).to_instant(),
+                },
+            ));
+            added += 1;
+        }
+        events.sort_by_key(|e| e.location);
+        cueutils::set_events(cue, events);
+    }
+    added
+}
+
+/// Clock time as minutes, seconds and tenths.
+pub fn format_time(us: u64) -> String {
+    let tenths = us / 100_000;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cueutils::testing::cue;
+
+    fn timecode_events(cue: &Cue) -> Vec<(usize, String)> {
+        cueutils::events(cue)
+            .iter()
+            .filter_map(|e| match e.event {
+                Some(EventDescription::TimecodeEvent { time }) => Some((
+                    e.location as usize,
+                    Timecode::from_instant(&time)?.to_string(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_drop_frame() {
+        let rate = FrameRate::Fps2997Drop;
+        // 00:00:59;29 is followed by 00:01:00;02
+        let tc = Timecode::from_hmsf(0, 0, 59, 29, rate);
+        assert_eq!(tc.frame, 1799);
+        assert_eq!(Timecode { frame: 1800, rate }.to_string(), "00:01:00;02");
+        // but every tenth minute keeps its first frames
+        let tc = Timecode::from_hmsf(0, 10, 0, 0, rate);
+        assert_eq!(tc.frame, 17982);
+        assert_eq!(tc.to_string(), "00:10:00;00");
+        for frame in [0, 1799, 1800, 17981, 17982, 107892, 1_000_000] {
+            let (h, m, s, f) = Timecode { frame, rate }.hmsf();
+            assert_eq!(Timecode::from_hmsf(h, m, s, f, rate).frame, frame);
+        }
+    }
+
+    #[test]
+    fn test_beat_times_through_repeat() {
+        // Timecode from the first beat, and the first four beats played twice
+        let mut cue = cue(8);
+        cue.events.push(Event::new(
+            0,
+            EventDescription::TimecodeEvent {
+                time: Timecode::from_hmsf(1, 0, 0, 0, FrameRate::Fps25).to_instant(),
+            },
+        ));
+        cue.events.push(Event::new(
+            3,
+            EventDescription::JumpEvent {
+                destination: 0,
+                requirement: common::event::JumpRequirement::JumpModeOn,
+                when_jumped: common::event::JumpModeChange::SetOff,
+                when_passed: common::event::JumpModeChange::SetOn,
+            },
+        ));
+
+        let BeatTimes { times, timecodes } = BeatTimes::new(&cue);
+        assert_eq!(times[3], Some(1_500_000));
+        assert_eq!(times[4], Some(4_000_000));
+        // The timecode starts over on the second time through the first beat
+        assert_eq!(timecodes[3].unwrap().to_string(), "01:00:01:12");
+        assert_eq!(timecodes[4].unwrap().to_string(), "01:00:02:00");
+    }
+
+    #[test]
+    fn test_beat_times_cache() {
+        let mut cache = BeatTimesCache::default();
+        let cue = cue(4);
+        let times = cache.get(1, 0, &cue);
+        assert!(Rc::ptr_eq(&times, &cache.get(1, 0, &cue)));
+        // Worked out again once the show has changed
+        assert!(!Rc::ptr_eq(&times, &cache.get(2, 0, &cue)));
+    }
+
+    fn chase(show: &mut Show, points: ChasePoints, policy: ChasePolicy) -> usize {
+        plan_chase(
+            show,
+            &ChasePlan {
+                start: Timecode::from_hmsf(1, 0, 0, 0, FrameRate::Fps25),
+                points,
+                policy,
+            },
+        )
+    }
+
+    #[test]
+    fn test_chase_policies() {
+        // Two cues of two seconds, the first with a timecode event to be replaced
+        let mut show = Show::default();
+        show.cues.push(cue(4));
+        show.cues.push(cue(4));
+        show.cues[0].events.push(Event::new(
+            2,
+            EventDescription::TimecodeEvent {
+                time: Timecode::from_hmsf(10, 0, 0, 0, FrameRate::Fps25).to_instant(),
+            },
+        ));
+
+        assert_eq!(
+            chase(&mut show, ChasePoints::CueStarts, ChasePolicy::Continuous),
+            2
+        );
+        assert_eq!(
+            timecode_events(&show.cues[0]),
+            [(0, "01:00:00:00".to_string())]
+        );
+        assert_eq!(
+            timecode_events(&show.cues[1]),
+            [(0, "01:00:02:00".to_string())]
+        );
+
+        chase(
+            &mut show,
+            ChasePoints::CueStarts,
+            ChasePolicy::RestartEachCue,
+        );
+        assert_eq!(
+            timecode_events(&show.cues[0]),
+            [(0, "01:00:00:00".to_string())]
+        );
+        assert_eq!(
+            timecode_events(&show.cues[1]),
+            [(0, "01:00:00:00".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_chase_rehearsal_marks() {
+        // Only the second cue has a rehearsal mark, the first still gets timecode from its start
+        let mut show = Show::default();
+        show.cues.push(cue(4));
+        show.cues.push(cue(4));
+        show.cues[1].events.push(Event::new(
+            2,
+            EventDescription::RehearsalMarkEvent {
+                label: common::mem::str::StaticString::new("A"),
+            },
+        ));
+
+        assert_eq!(
+            chase(
+                &mut show,
+                ChasePoints::RehearsalMarks,
+                ChasePolicy::Continuous
+            ),
+            2
+        );
+        assert_eq!(
+            timecode_events(&show.cues[0]),
+            [(0, "01:00:00:00".to_string())]
+        );
+        assert_eq!(
+            timecode_events(&show.cues[1]),
+            [(2, "01:00:03:00".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_real_time() {
+        // An hour of drop frame timecode is an hour of clock time, give or take a frame
+        let tc = Timecode::from_hmsf(1, 0, 0, 0, FrameRate::Fps2997Drop);
+        assert!(tc.to_us().abs_diff(3_600_000_000) < 40_000);
+        let tc = Timecode::from_hmsf(0, 0, 10, 0, FrameRate::Fps25).after(1_020_000);
+        assert_eq!(tc.to_string(), "00:00:11:00");
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{clip::ClipManager, cueutils, tempomap::TempoMap, timecode::FrameRate};
+use common::{
+    cue::Show,
+    event::{EventDescription, PauseEventBehaviour},
+};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    pub cue_idx: usize,
+    pub beat_idx: Option<usize>,
+    pub message: String,
+}
+
+impl Problem {
+    fn error(cue_idx: usize, beat_idx: Option<usize>, message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            cue_idx,
+            beat_idx,
+            message,
+        }
+    }
+
+    fn warning(cue_idx: usize, beat_idx: Option<usize>, message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            cue_idx,
+            beat_idx,
+            message,
+        }
+    }
+}
+
+/// Check a show for structural errors.
+///
+/// Clip references are only checked if `clips` is given, since a show loaded from JSON has no
+/// playback media to check against.
+pub fn validate_show(show: &Show, clips: Option<&ClipManager>) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    let mut idents: HashMap<String, usize> = HashMap::new();
+    for (cue_idx, cue) in show.cues.iter().enumerate() {
+        let ident = cue.metadata.human_ident.str().to_string();
+        if let Some(&first) = idents.get(&ident) {
+            problems.push(Problem::warning(
+                cue_idx,
+                None,
+                format!(
+                    "Identifier '{ident}' of cue '{}' (#{}) is already used by cue '{}' (#{})",
+                    cue.metadata.name.str(),
+                    cue_idx + 1,
+                    show.cues[first].metadata.name.str(),
+                    first + 1,
+                ),
+            ));
+        } else {
+            idents.insert(ident, cue_idx);
+        }
+
+        if cue.beats.is_empty() {
+            problems.push(Problem::warning(
+                cue_idx,
+                None,
+                "Cue has no beats".to_string(),
+            ));
+        }
+
+        let num_beats = cue.beats.len();
+        let mut events = cueutils::events(cue);
+        events.sort_by_key(|e| e.location);
+        let mut running_channels: HashSet<usize> = HashSet::new();
+        for event in events {
+            let loc = event.location as usize;
+            let beat_idx = Some(loc.min(num_beats.saturating_sub(1)));
+            if loc >= num_beats {
+                problems.push(Problem::error(
+                    cue_idx,
+                    beat_idx,
+                    format!(
+                        "{} at beat {loc} is past the end of the cue ({num_beats} beats)",
+                        event
+                            .event
+                            .map(|e| e.get_name().to_string())
+                            .unwrap_or_default()
+                    ),
+                ));
+            }
+            match event.event {
+                Some(EventDescription::JumpEvent { destination, .. }) => {
+                    if destination as usize >= num_beats {
+                        problems.push(Problem::error(
+                            cue_idx,
+                            beat_idx,
+                            format!(
+                                "Jump destination {destination} is past the end of the cue ({num_beats} beats)"
+                            ),
+                        ));
+                    }
+                }
+                Some(EventDescription::PauseEvent {
+                    behaviour: PauseEventBehaviour::Jump { destination },
+                }) => {
+                    if destination as usize >= num_beats {
+                        problems.push(Problem::error(
+                            cue_idx,
+                            beat_idx,
+                            format!(
+                                "Pause destination {destination} is past the end of the cue ({num_beats} beats)"
+                            ),
+                        ));
+                    }
+                }
+                Some(EventDescription::GradualTempoChangeEvent { length, .. }) => {
+                    if loc + length as usize > num_beats {
+                        problems.push(Problem::error(
+                            cue_idx,
+                            beat_idx,
+                            format!(
+                                "Gradual tempo change of {length} beats runs past the end of the cue"
+                            ),
+                        ));
+                    }
+                }
+                Some(EventDescription::PlaybackEvent {
+                    channel_idx,
+                    clip_idx,
+                    ..
+                }) => {
+                    if let Some(clips) = clips
+                        && !clips
+                            .clips
+                            .contains_key(&(channel_idx as usize, clip_idx as usize))
+                    {
+                        problems.push(Problem::error(
+                            cue_idx,
+                            beat_idx,
+                            format!("No audio clip {clip_idx} on channel {channel_idx}"),
+                        ));
+                    }
+                    running_channels.insert(channel_idx as usize);
+                }
+                Some(EventDescription::TimecodeEvent { time })
+                    if FrameRate::from_stored(time.frame_rate as u32).is_none() =>
+                {
+                    problems.push(Problem::warning(
+                        cue_idx,
+                        beat_idx,
+                        format!(
+                            "Timecode at {} frames per second, which the editor can't run",
+                            time.frame_rate
+                        ),
+                    ));
+                }
+                Some(EventDescription::PlaybackStopEvent { channel_idx }) => {
+                    if !running_channels.remove(&(channel_idx as usize)) {
+                        problems.push(Problem::warning(
+                            cue_idx,
+                            beat_idx,
+                            format!("Playback stop on channel {channel_idx}, which isn't playing"),
+                        ));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        for conflict in TempoMap::new(cue).tempos(cue).1 {
+            problems.push(Problem::warning(
+                cue_idx,
+                Some(conflict.beat_idx),
+                conflict.message,
+            ));
+        }
+    }
+
+    problems.sort_by_key(|p| (p.cue_idx, p.beat_idx, p.severity));
+    problems
+}